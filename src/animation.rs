@@ -0,0 +1,309 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use failure::{bail, Error};
+
+use crate::types::{Point3, Scalar, Vector3};
+
+/// Placement of the camera at a given frame of an animation
+#[derive(Debug, Clone)]
+pub struct Keyframe {
+    pub frame: Scalar,
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    /// Field of view in degrees, as used by the projection
+    pub fov: Scalar,
+    /// Distance in front of the camera which is in focus
+    pub focus_distance: Scalar,
+}
+
+/// Catmull-Rom spline through four values at parameter t between the middle two
+fn catmull_rom(p0: Scalar, p1: Scalar, p2: Scalar, p3: Scalar, t: Scalar) -> Scalar {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+/// Catmull-Rom spline through four points at parameter t between the middle two
+fn catmull_rom_point(p: [&Point3; 4], t: Scalar) -> Point3 {
+    Point3::new(
+        catmull_rom(p[0].x, p[1].x, p[2].x, p[3].x, t),
+        catmull_rom(p[0].y, p[1].y, p[2].y, p[3].y, t),
+        catmull_rom(p[0].z, p[1].z, p[2].z, p[3].z, t),
+    )
+}
+
+/// Path of the camera through an animation, passing smoothly through a series of keyframes
+#[derive(Debug, Clone)]
+pub struct CameraPath {
+    /// Keyframes in order of frame
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    /// Path holding the camera still at a single keyframe
+    pub fn still(keyframe: Keyframe) -> Self {
+        Self {
+            keyframes: vec![keyframe],
+        }
+    }
+
+    /// Path circling the camera once around the vertical axis through the point it looks at
+    /// over a number of frames, as for a turntable. The last frame stops a step short of where
+    /// the first began, so that the sequence loops without repeating a frame.
+    pub fn turntable(keyframe: Keyframe, frames: u32) -> Self {
+        let offset = keyframe.lookfrom - keyframe.lookat;
+        let keyframes = (0..frames.max(1))
+            .map(|i| {
+                let angle = 2.0 * std::f32::consts::PI * i as Scalar / frames.max(1) as Scalar;
+                let (sin, cos) = angle.sin_cos();
+                let rotated = Vector3::new(
+                    offset.x * cos + offset.z * sin,
+                    offset.y,
+                    offset.z * cos - offset.x * sin,
+                );
+
+                Keyframe {
+                    frame: keyframe.frame + i as Scalar,
+                    lookfrom: keyframe.lookat + rotated,
+                    ..keyframe.clone()
+                }
+            })
+            .collect();
+
+        Self { keyframes }
+    }
+
+    /// Load a path from a file of keyframes, one per line, each holding the frame, position,
+    /// point looked at, field of view, and focus distance, with a focus distance of zero
+    /// focusing on the point looked at
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut keyframes = vec![];
+
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let v = line
+                .split_whitespace()
+                .map(|s| s.parse::<Scalar>())
+                .collect::<Result<Vec<_>, _>>()?;
+            if v.len() != 9 || v.iter().any(|x| !x.is_finite()) || v[7] <= 0.0 || v[7] > 360.0 {
+                bail!("invalid keyframe: {}", line);
+            }
+
+            let lookfrom = Point3::new(v[1], v[2], v[3]);
+            let lookat = Point3::new(v[4], v[5], v[6]);
+            keyframes.push(Keyframe {
+                frame: v[0],
+                lookfrom,
+                lookat,
+                fov: v[7],
+                focus_distance: if v[8] > 0.0 {
+                    v[8]
+                } else {
+                    (lookat - lookfrom).magnitude()
+                },
+            });
+        }
+
+        if keyframes.is_empty() {
+            bail!("camera path has no keyframes");
+        }
+        keyframes.sort_by(|a, b| a.frame.partial_cmp(&b.frame).unwrap());
+
+        Ok(Self { keyframes })
+    }
+
+    /// First and last whole frames spanning the keyframes of the path
+    pub fn frames(&self) -> (u32, u32) {
+        let first = self.keyframes[0].frame;
+        let last = self.keyframes[self.keyframes.len() - 1].frame;
+        (first.max(0.0).floor() as u32, last.max(0.0).ceil() as u32)
+    }
+
+    /// Placement of the camera at a frame, interpolated between the surrounding keyframes and
+    /// held at the first and last keyframes outside of them
+    pub fn at(&self, frame: Scalar) -> Keyframe {
+        let keys = &self.keyframes;
+        let last = keys.len() - 1;
+        let next = keys
+            .iter()
+            .position(|k| k.frame > frame)
+            .unwrap_or(last + 1);
+        if next == 0 {
+            return Keyframe {
+                frame,
+                ..keys[0].clone()
+            };
+        }
+        if next > last {
+            return Keyframe {
+                frame,
+                ..keys[last].clone()
+            };
+        }
+
+        let i = next - 1;
+        let k = [
+            &keys[i.saturating_sub(1)],
+            &keys[i],
+            &keys[next],
+            &keys[(next + 1).min(last)],
+        ];
+        let t = (frame - k[1].frame) / (k[2].frame - k[1].frame);
+
+        Keyframe {
+            frame,
+            lookfrom: catmull_rom_point(
+                [
+                    &k[0].lookfrom,
+                    &k[1].lookfrom,
+                    &k[2].lookfrom,
+                    &k[3].lookfrom,
+                ],
+                t,
+            ),
+            lookat: catmull_rom_point([&k[0].lookat, &k[1].lookat, &k[2].lookat, &k[3].lookat], t),
+            fov: catmull_rom(k[0].fov, k[1].fov, k[2].fov, k[3].fov, t),
+            focus_distance: catmull_rom(
+                k[0].focus_distance,
+                k[1].focus_distance,
+                k[2].focus_distance,
+                k[3].focus_distance,
+                t,
+            ),
+        }
+    }
+}
+
+/// Path of the image for a frame of an animation, replacing a run of # characters in the
+/// given path with the zero padded frame number, or inserting the number before the extension
+pub fn frame_path(output: &str, frame: u32) -> String {
+    if let Some(start) = output.find('#') {
+        let width = output[start..].chars().take_while(|&c| c == '#').count();
+        return format!(
+            "{}{:0width$}{}",
+            &output[..start],
+            frame,
+            &output[start + width..],
+            width = width
+        );
+    }
+
+    let path = Path::new(output);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => format!("{}_{:04}.{}", stem, frame, extension),
+        None => format!("{}_{:04}", stem, frame),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Parse a range of frames given as a single frame number or the first and last frames
+/// separated by a dash, such as 1-120
+pub fn parse_frames(range: &str) -> Result<(u32, u32), Error> {
+    let mut parts = range.splitn(2, '-');
+    let first = parts.next().unwrap_or("").trim().parse::<u32>()?;
+    let last = match parts.next() {
+        Some(last) => last.trim().parse::<u32>()?,
+        None => first,
+    };
+    if last < first {
+        bail!("last frame {} comes before first frame {}", last, first);
+    }
+
+    Ok((first, last))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_frames_and_ranges() {
+        assert_eq!(parse_frames("7").unwrap(), (7, 7));
+        assert_eq!(parse_frames("1-120").unwrap(), (1, 120));
+        assert_eq!(parse_frames(" 3 - 5 ").unwrap(), (3, 5));
+    }
+
+    #[test]
+    fn rejects_malformed_frame_ranges() {
+        assert!(parse_frames("").is_err());
+        assert!(parse_frames("a-3").is_err());
+        assert!(parse_frames("1-").is_err());
+        assert!(parse_frames("-1").is_err());
+        assert!(parse_frames("5-3").is_err());
+    }
+
+    #[test]
+    fn pads_frame_numbers_in_place_of_hashes() {
+        assert_eq!(frame_path("out_###.png", 7), "out_007.png");
+        assert_eq!(frame_path("#.png", 42), "42.png");
+        assert_eq!(frame_path("f##/x.png", 1234), "f1234/x.png");
+    }
+
+    #[test]
+    fn inserts_frame_numbers_before_the_extension() {
+        assert_eq!(frame_path("out.png", 7), "out_0007.png");
+        assert_eq!(frame_path("dir/out.png", 12), "dir/out_0012.png");
+        assert_eq!(frame_path("out", 3), "out_0003");
+    }
+
+    #[test]
+    fn turntable_loops_without_repeating_a_frame() {
+        let keyframe = Keyframe {
+            frame: 0.0,
+            lookfrom: Point3::new(10.0, 2.0, 0.0),
+            lookat: Point3::origin(),
+            fov: 20.0,
+            focus_distance: 10.0,
+        };
+        let path = CameraPath::turntable(keyframe, 4);
+        assert_eq!(path.keyframes.len(), 4);
+
+        let quarter = path.at(1.0).lookfrom;
+        assert!((quarter - Point3::new(0.0, 2.0, -10.0)).magnitude() < 1e-4);
+        let last = path.at(3.0).lookfrom;
+        assert!((last - Point3::new(0.0, 2.0, 10.0)).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn rejects_keyframes_without_a_field_of_view() {
+        let path = std::env::temp_dir().join(format!("rtxon-path-{}.txt", std::process::id()));
+        for fov in &["20", "0", "-10", "400"] {
+            std::fs::write(&path, format!("0 10 2 0 0 0 0 {} 0\n", fov)).unwrap();
+            assert_eq!(CameraPath::load(&path).is_ok(), *fov == "20", "{}", fov);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn path_spans_the_frames_of_its_keyframes() {
+        let keyframe = Keyframe {
+            frame: 2.5,
+            lookfrom: Point3::new(10.0, 2.0, 0.0),
+            lookat: Point3::origin(),
+            fov: 20.0,
+            focus_distance: 10.0,
+        };
+        let path = CameraPath {
+            keyframes: vec![
+                keyframe.clone(),
+                Keyframe {
+                    frame: 7.25,
+                    ..keyframe
+                },
+            ],
+        };
+        assert_eq!(path.frames(), (2, 8));
+    }
+}
@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use rand::random;
+
+use crate::materials::{Material, ScatteredRay};
+use crate::media::Interior;
+use crate::shapes::HitResult;
+use crate::textures::Texture;
+use crate::types::{Color, Ray, Scalar, Vector3};
+
+/// Material blending two others by a weight, which may vary over the surface as with patches of
+/// rust on metal. A weight of 0.0 gives the first material and 1.0 the second.
+#[derive(Debug, Clone)]
+pub struct Mix {
+    pub a: Arc<dyn Material>,
+    pub b: Arc<dyn Material>,
+    pub weight: Arc<dyn Texture>,
+}
+
+impl Mix {
+    /// Weight of the second material at the point of intersection
+    fn weight(&self, hit: &HitResult) -> Scalar {
+        self.weight.scalar(&hit.uv, &hit.p).clamp(0.0, 1.0)
+    }
+}
+
+impl Material for Mix {
+    /// Calculate scattered ray generated by an incoming ray interacting with this surface
+    fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
+        // Choosing either material in proportion to its weight leaves the attenuation unscaled
+        if random::<Scalar>() < self.weight(hit) {
+            self.b.scatter(ray, hit)
+        } else {
+            self.a.scatter(ray, hit)
+        }
+    }
+
+    /// Light emitted by this surface at the point of intersection
+    fn emitted(&self, hit: &HitResult) -> Color {
+        let w = self.weight(hit);
+        self.a.emitted(hit) * (1.0 - w) + self.b.emitted(hit) * w
+    }
+
+    /// Evaluate the fraction of light arriving from a direction which is scattered along the
+    /// incoming ray, including the cosine term, available only if it is for both materials
+    fn eval(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Color> {
+        let w = self.weight(hit);
+        let a = self.a.eval(ray, hit, direction)?;
+        let b = self.b.eval(ray, hit, direction)?;
+        Some(a * (1.0 - w) + b * w)
+    }
+
+    /// Probability density with which scatter() would choose the given direction
+    fn pdf(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Scalar> {
+        let w = self.weight(hit);
+        let a = self.a.pdf(ray, hit, direction)?;
+        let b = self.b.pdf(ray, hit, direction)?;
+        Some(a * (1.0 - w) + b * w)
+    }
+
+    /// Medium filling the inside of closed shapes made of this material. A shape has a single
+    /// inside wherever its surface is weighted, so this is the medium of the first material
+    /// with one, which light refracted through either material enters.
+    fn interior(&self) -> Option<&Interior> {
+        self.a.interior().or_else(|| self.b.interior())
+    }
+
+    /// Fraction of rays stopped by this surface rather than passing through it
+    fn opacity(&self, hit: &HitResult) -> Scalar {
+        let w = self.weight(hit);
+        self.a.opacity(hit) * (1.0 - w) + self.b.opacity(hit) * w
+    }
+
+    /// Color of the surface apart from lighting, guiding the denoiser
+    fn albedo(&self, hit: &HitResult) -> Color {
+        let w = self.weight(hit);
+        self.a.albedo(hit) * (1.0 - w) + self.b.albedo(hit) * w
+    }
+}
+
+/// Material presenting the same face to rays from either side of a surface, by flipping the
+/// normal toward the incoming ray, for thin surfaces such as leaves or sheets of paper
+#[derive(Debug, Clone)]
+pub struct TwoSided {
+    pub material: Arc<dyn Material>,
+}
+
+impl TwoSided {
+    /// Hit as seen by the underlying material, with the normal facing the incoming ray
+    fn shade(&self, ray: &Ray, hit: &HitResult) -> HitResult {
+        let mut shaded = hit.clone();
+        if ray.direction.dot(&hit.normal) > 0.0 {
+            shaded.normal = -hit.normal;
+        }
+        shaded
+    }
+}
+
+impl Material for TwoSided {
+    /// Calculate scattered ray generated by an incoming ray interacting with this surface
+    fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
+        self.material.scatter(ray, &self.shade(ray, hit))
+    }
+
+    /// Light emitted by this surface at the point of intersection
+    fn emitted(&self, hit: &HitResult) -> Color {
+        self.material.emitted(hit)
+    }
+
+    /// Evaluate the fraction of light arriving from a direction which is scattered along the
+    /// incoming ray, including the cosine term
+    fn eval(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Color> {
+        self.material.eval(ray, &self.shade(ray, hit), direction)
+    }
+
+    /// Probability density with which scatter() would choose the given direction
+    fn pdf(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Scalar> {
+        self.material.pdf(ray, &self.shade(ray, hit), direction)
+    }
+
+    /// Medium filling the inside of closed shapes made of the underlying material
+    fn interior(&self) -> Option<&Interior> {
+        self.material.interior()
+    }
+
+    /// Fraction of rays stopped by this surface rather than passing through it
+    fn opacity(&self, hit: &HitResult) -> Scalar {
+        self.material.opacity(hit)
+    }
+
+    /// Color of the surface apart from lighting, guiding the denoiser
+    fn albedo(&self, hit: &HitResult) -> Color {
+        self.material.albedo(hit)
+    }
+}
+
+/// Material cutting holes in another material where a mask texture is below one, letting rays
+/// pass through unaffected. Values between zero and one are partially transparent.
+#[derive(Debug, Clone)]
+pub struct AlphaMask {
+    pub mask: Arc<dyn Texture>,
+    pub material: Arc<dyn Material>,
+}
+
+impl Material for AlphaMask {
+    /// Calculate scattered ray generated by an incoming ray interacting with this surface
+    fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
+        self.material.scatter(ray, hit)
+    }
+
+    /// Light emitted by this surface at the point of intersection
+    fn emitted(&self, hit: &HitResult) -> Color {
+        self.material.emitted(hit)
+    }
+
+    /// Evaluate the fraction of light arriving from a direction which is scattered along the
+    /// incoming ray, including the cosine term
+    fn eval(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Color> {
+        self.material.eval(ray, hit, direction)
+    }
+
+    /// Probability density with which scatter() would choose the given direction
+    fn pdf(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Scalar> {
+        self.material.pdf(ray, hit, direction)
+    }
+
+    /// Medium filling the inside of closed shapes made of the underlying material
+    fn interior(&self) -> Option<&Interior> {
+        self.material.interior()
+    }
+
+    /// Fraction of rays stopped by this surface rather than passing through it
+    fn opacity(&self, hit: &HitResult) -> Scalar {
+        let mask = self.mask.scalar(&hit.uv, &hit.p).clamp(0.0, 1.0);
+        mask * self.material.opacity(hit)
+    }
+
+    /// Color of the surface apart from lighting, guiding the denoiser
+    fn albedo(&self, hit: &HitResult) -> Color {
+        self.material.albedo(hit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materials::Lambertian;
+    use crate::subsurface::Subsurface;
+    use crate::textures::Constant;
+
+    fn subsurface() -> Arc<dyn Material> {
+        Arc::new(Subsurface::new(
+            Color::new(0.8, 0.5, 0.4, 1.0),
+            Color::new(0.1, 0.1, 0.1, 1.0),
+            1.4,
+            Arc::new(Constant::scalar(0.3)),
+            0.0,
+        ))
+    }
+
+    fn lambertian() -> Arc<dyn Material> {
+        Arc::new(Lambertian {
+            albedo: Arc::new(Constant::scalar(0.5)),
+        })
+    }
+
+    #[test]
+    fn mix_has_the_interior_of_either_material() {
+        for &(a, b) in &[(true, false), (false, true)] {
+            let pick = |s| if s { subsurface() } else { lambertian() };
+            let mix = Mix {
+                a: pick(a),
+                b: pick(b),
+                weight: Arc::new(Constant::scalar(0.5)),
+            };
+            assert!(mix.interior().is_some());
+        }
+        let mix = Mix {
+            a: lambertian(),
+            b: lambertian(),
+            weight: Arc::new(Constant::scalar(0.5)),
+        };
+        assert!(mix.interior().is_none());
+    }
+
+    #[test]
+    fn wrappers_forward_the_interior() {
+        let two_sided = TwoSided {
+            material: subsurface(),
+        };
+        assert!(two_sided.interior().is_some());
+        let masked = AlphaMask {
+            mask: Arc::new(Constant::scalar(0.5)),
+            material: subsurface(),
+        };
+        assert!(masked.interior().is_some());
+    }
+}
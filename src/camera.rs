@@ -1,6 +1,10 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
 use rand::random;
 
-use crate::types::{Point3, Ray, Scalar, Vector3};
+use crate::textures::Texture;
+use crate::types::{Point2, Point3, Ray, Scalar, Vector3};
 
 /// Sample a random point in the unit disk via rejection
 fn random_in_unit_disk() -> Vector3 {
@@ -14,37 +18,142 @@ fn random_in_unit_disk() -> Vector3 {
     p
 }
 
-/// Adjustable camera for generating eye rays according to given parameters
+/// Most points tried when sampling an aperture mask by rejection before falling back on its
+/// center, which keeps masks with little or no open area from stalling rendering
+const MAX_MASK_ATTEMPTS: u32 = 1024;
+
+/// Shape of the opening of a lens aperture, which gives out of focus highlights their shape
+#[derive(Debug, Clone)]
+pub enum ApertureShape {
+    /// Perfectly round opening
+    Circular,
+    /// Regular polygon formed by a number of straight blades, rotated by an angle in degrees
+    Polygon { blades: u32, rotation: Scalar },
+    /// Opening given by a texture over the square bounding the aperture, open where it is one
+    /// and closed where it is zero, as for a card with a shape cut out held over the lens
+    Mask(Arc<dyn Texture>),
+}
+
+/// Aperture of a lens through which rays pass on their way to the film
+#[derive(Debug, Clone)]
+pub struct Aperture {
+    pub diameter: Scalar,
+    pub shape: ApertureShape,
+    /// Horizontal squeeze of an anamorphic lens, 1.0 for spherical lenses, stretching the
+    /// aperture vertically so that out of focus highlights become tall ovals
+    pub squeeze: Scalar,
+}
+
+impl Aperture {
+    /// Sample a random point in the aperture, in the plane of the lens
+    pub fn sample(&self) -> Vector3 {
+        let p = match &self.shape {
+            ApertureShape::Circular => random_in_unit_disk(),
+            ApertureShape::Polygon { blades, rotation } => {
+                random_in_polygon(*blades, rotation * PI / 180.0)
+            }
+            ApertureShape::Mask(mask) => random_in_mask(mask.as_ref()),
+        };
+
+        let radius = self.diameter / 2.0;
+        Vector3::new(p.x * radius / self.squeeze, p.y * radius, 0.0)
+    }
+}
+
+/// Sample a random point in a regular polygon inscribed in the unit circle by choosing one of
+/// the triangles between its center and edges
+fn random_in_polygon(sides: u32, rotation: Scalar) -> Vector3 {
+    let k = ((random::<Scalar>() * sides as Scalar) as u32).min(sides - 1);
+    let corner = |i: u32| {
+        let angle = rotation + 2.0 * PI * i as Scalar / sides as Scalar;
+        Vector3::new(angle.cos(), angle.sin(), 0.0)
+    };
+
+    let a = random::<Scalar>().sqrt();
+    let b = random::<Scalar>();
+    corner(k) * (a * (1.0 - b)) + corner(k + 1) * (a * b)
+}
+
+/// Sample a random point in the square from -1.0 to 1.0 with density proportional to a mask
+/// texture, via rejection
+fn random_in_mask(mask: &dyn Texture) -> Vector3 {
+    for _ in 0..MAX_MASK_ATTEMPTS {
+        let uv = Point2::new(random::<Scalar>(), random::<Scalar>());
+        if random::<Scalar>() < mask.scalar(&uv, &Point3::origin()) {
+            return Vector3::new(2.0 * uv.x - 1.0, 2.0 * uv.y - 1.0, 0.0);
+        }
+    }
+
+    Vector3::zeros()
+}
+
+/// Camera generates eye rays through points on the image
+pub trait Camera: Send + Sync + std::fmt::Debug {
+    /// Get a ray through the image at coordinates s and t, running from 0.0 to 1.0 rightward and
+    /// upward, with the weight of the light it carries to the film, or None where the
+    /// projection does not cover the image. Rays blocked within the camera, which cover the
+    /// image but carry no light, have a weight of zero.
+    fn get_ray(&self, s: Scalar, t: Scalar) -> Option<(Ray, Scalar)>;
+}
+
+/// Position and orientation of a camera, with u pointing right, v up, and w backward
+#[derive(Debug, Clone)]
+pub struct View {
+    pub origin: Point3,
+    pub u: Vector3,
+    pub v: Vector3,
+    pub w: Vector3,
+}
+
+impl View {
+    /// Create a view from an origin looking toward a point, with the given direction upward
+    pub fn new(origin: Point3, lookat: Point3, up: Vector3) -> Self {
+        let w = (origin - lookat).normalize();
+        let u = up.cross(&w).normalize();
+        let v = w.cross(&u);
+
+        Self { origin, u, v, w }
+    }
+
+    /// The same view moved sideways by a distance to the right, as for one of a pair of eyes
+    pub fn offset(&self, distance: Scalar) -> Self {
+        Self {
+            origin: self.origin + self.u * distance,
+            ..self.clone()
+        }
+    }
+
+    /// Express a direction relative to the view, with z pointing backward, in world space
+    pub fn to_world(&self, d: &Vector3) -> Vector3 {
+        self.u * d.x + self.v * d.y + self.w * d.z
+    }
+}
+
+/// Perspective camera with a thin lens, giving depth of field for nonzero apertures
 #[derive(Debug, Clone)]
-pub struct Camera {
+pub struct ThinLens {
     origin: Point3,
     lower_left_corner: Point3,
     horizontal: Vector3,
     vertical: Vector3,
     u: Vector3,
     v: Vector3,
-    w: Vector3,
-    lens_radius: Scalar,
+    aperture: Aperture,
 }
 
-impl Camera {
+impl ThinLens {
     /// Create a new camera
     pub fn new(
-        origin: Point3,
-        lookat: Point3,
-        up: Vector3,
+        view: &View,
         vfov_degrees: Scalar,
         aspect_ratio: Scalar,
-        aperture: Scalar,
+        aperture: Aperture,
         focal_length: Scalar,
     ) -> Self {
-        let theta = vfov_degrees * std::f32::consts::PI / 180.0;
+        let theta = vfov_degrees * PI / 180.0;
         let half_height = (theta / 2.0).tan();
         let half_width = aspect_ratio * half_height;
-
-        let w = (origin - lookat).normalize();
-        let u = up.cross(&w).normalize();
-        let v = w.cross(&u);
+        let View { origin, u, v, w } = view.clone();
 
         Self {
             origin,
@@ -56,20 +165,202 @@ impl Camera {
             vertical: 2.0 * half_height * focal_length * v,
             u,
             v,
-            w,
-            lens_radius: aperture / 2.0,
+            aperture,
         }
     }
+}
 
+impl Camera for ThinLens {
     /// Get a ray from origin intersecting viewing plane at coordinates s and t
-    pub fn get_ray(&self, s: Scalar, t: Scalar) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disk();
+    fn get_ray(&self, s: Scalar, t: Scalar) -> Option<(Ray, Scalar)> {
+        let rd = self.aperture.sample();
         let offset = rd.x * self.u + rd.y * self.v;
-        Ray::new(
-            self.origin + offset,
-            self.lower_left_corner.coords + s * self.horizontal + t * self.vertical
-                - self.origin.coords
-                - offset,
-        )
+        Some((
+            Ray::new(
+                self.origin + offset,
+                self.lower_left_corner.coords + s * self.horizontal + t * self.vertical
+                    - self.origin.coords
+                    - offset,
+            ),
+            1.0,
+        ))
+    }
+}
+
+/// Orthographic camera casting parallel rays from a rectangle of the given height in world
+/// units, free of perspective as is common for architectural elevations
+#[derive(Debug, Clone)]
+pub struct Orthographic {
+    lower_left_corner: Point3,
+    horizontal: Vector3,
+    vertical: Vector3,
+    direction: Vector3,
+}
+
+impl Orthographic {
+    /// Create a new camera
+    pub fn new(view: &View, height: Scalar, aspect_ratio: Scalar) -> Self {
+        let horizontal = height * aspect_ratio * view.u;
+        let vertical = height * view.v;
+
+        Self {
+            lower_left_corner: view.origin - horizontal / 2.0 - vertical / 2.0,
+            horizontal,
+            vertical,
+            direction: -view.w,
+        }
+    }
+}
+
+impl Camera for Orthographic {
+    /// Get a ray from the point of the viewing rectangle at coordinates s and t
+    fn get_ray(&self, s: Scalar, t: Scalar) -> Option<(Ray, Scalar)> {
+        Some((
+            Ray::new(
+                self.lower_left_corner + s * self.horizontal + t * self.vertical,
+                self.direction,
+            ),
+            1.0,
+        ))
+    }
+}
+
+/// Panoramic camera covering all directions with an equirectangular projection, longitude
+/// running across the image with the view direction in the middle and latitude up it. A nonzero
+/// eye offset gives one eye of an omni-directional stereo pair, whose rays start on a circle
+/// around the origin so that the eye is always to the side of the direction looked in.
+#[derive(Debug, Clone)]
+pub struct Equirectangular {
+    pub view: View,
+    /// Signed distance of the eye to the right of the origin, negative for the left eye
+    pub eye_offset: Scalar,
+}
+
+impl Camera for Equirectangular {
+    /// Get a ray in the direction at longitude and latitude given by coordinates s and t
+    fn get_ray(&self, s: Scalar, t: Scalar) -> Option<(Ray, Scalar)> {
+        let phi = (s - 0.5) * 2.0 * PI;
+        let theta = (t - 0.5) * PI;
+        let d = Vector3::new(
+            theta.cos() * phi.sin(),
+            theta.sin(),
+            -theta.cos() * phi.cos(),
+        );
+        let eye = Vector3::new(phi.cos(), 0.0, phi.sin()) * self.eye_offset;
+
+        Some((
+            Ray::new(
+                self.view.origin + self.view.to_world(&eye),
+                self.view.to_world(&d),
+            ),
+            1.0,
+        ))
+    }
+}
+
+/// Faces of a cube map as forward, right, and up directions relative to the view, laid out in
+/// a 3x2 grid with left, front, and right across the top, and back, up, and down below
+const CUBE_FACES: [[[Scalar; 3]; 3]; 6] = [
+    [[-1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]],
+    [[0.0, 0.0, -1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+    [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]],
+    [[0.0, 0.0, 1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+    [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+    [[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]],
+];
+
+/// Panoramic camera rendering the six 90 degree faces of a cube around it, for environment
+/// maps and VR previews, best used with an image aspect ratio of 3:2
+#[derive(Debug, Clone)]
+pub struct CubeMap {
+    pub view: View,
+}
+
+impl Camera for CubeMap {
+    /// Get a ray through the point of the face of the cube at coordinates s and t
+    fn get_ray(&self, s: Scalar, t: Scalar) -> Option<(Ray, Scalar)> {
+        let column = ((s * 3.0) as usize).min(2);
+        let row = (((1.0 - t) * 2.0) as usize).min(1);
+        let [forward, right, up] = CUBE_FACES[row * 3 + column];
+
+        // Position within the face from -1.0 to 1.0
+        let a = (s * 3.0 - column as Scalar) * 2.0 - 1.0;
+        let b = (t * 2.0 - (1 - row) as Scalar) * 2.0 - 1.0;
+        let d = Vector3::from(forward) + Vector3::from(right) * a + Vector3::from(up) * b;
+
+        Some((Ray::new(self.view.origin, self.view.to_world(&d)), 1.0))
+    }
+}
+
+/// Mapping from angle off the optical axis to distance from the center of a fisheye image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FisheyeMapping {
+    /// Distance proportional to angle, preserving angular distances along radial lines
+    Equidistant,
+    /// Distance proportional to the sine of half the angle, preserving solid angles
+    Equisolid,
+}
+
+/// Fisheye camera projecting a field of view of up to 360 degrees onto a circle filling the
+/// shorter dimension of the image, leaving the corners uncovered
+#[derive(Debug, Clone)]
+pub struct Fisheye {
+    pub view: View,
+    pub fov_degrees: Scalar,
+    pub aspect_ratio: Scalar,
+    pub mapping: FisheyeMapping,
+}
+
+impl Camera for Fisheye {
+    /// Get a ray in the direction imaged at coordinates s and t
+    fn get_ray(&self, s: Scalar, t: Scalar) -> Option<(Ray, Scalar)> {
+        let (x, y) = if self.aspect_ratio >= 1.0 {
+            ((2.0 * s - 1.0) * self.aspect_ratio, 2.0 * t - 1.0)
+        } else {
+            (2.0 * s - 1.0, (2.0 * t - 1.0) / self.aspect_ratio)
+        };
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+
+        let theta_max = (self.fov_degrees * PI / 360.0).min(PI);
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * theta_max,
+            FisheyeMapping::Equisolid => 2.0 * (r * (theta_max / 2.0).sin()).asin(),
+        };
+        let (sin_phi, cos_phi) = if r > 0.0 { (y / r, x / r) } else { (0.0, 1.0) };
+        let d = Vector3::new(theta.sin() * cos_phi, theta.sin() * sin_phi, -theta.cos());
+
+        Some((Ray::new(self.view.origin, self.view.to_world(&d)), 1.0))
+    }
+}
+
+/// Arrangement of the images of the two eyes of a stereo pair within the output image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StereoLayout {
+    /// Left eye on the left half and right eye on the right half
+    SideBySide,
+    /// Left eye on the top half and right eye on the bottom half
+    TopBottom,
+}
+
+/// Pair of cameras for the left and right eyes, each rendering to its own half of the image
+#[derive(Debug, Clone)]
+pub struct Stereo {
+    pub left: Arc<dyn Camera>,
+    pub right: Arc<dyn Camera>,
+    pub layout: StereoLayout,
+}
+
+impl Camera for Stereo {
+    /// Get a ray from the camera of the eye whose half of the image contains coordinates s and t
+    fn get_ray(&self, s: Scalar, t: Scalar) -> Option<(Ray, Scalar)> {
+        match self.layout {
+            StereoLayout::SideBySide if s < 0.5 => self.left.get_ray(s * 2.0, t),
+            StereoLayout::SideBySide => self.right.get_ray(s * 2.0 - 1.0, t),
+            StereoLayout::TopBottom if t >= 0.5 => self.left.get_ray(s, t * 2.0 - 1.0),
+            StereoLayout::TopBottom => self.right.get_ray(s, t * 2.0),
+        }
     }
 }
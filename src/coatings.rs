@@ -0,0 +1,254 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use rand::random;
+
+use crate::materials::{Material, ScatterKind, ScatteredRay};
+use crate::microfacet::{beer_lambert, reflect, refract};
+use crate::shapes::HitResult;
+use crate::spectrum::{cie_xyz, xyz_to_rgb, LAMBDA_MAX, LAMBDA_MIN};
+use crate::textures::Texture;
+use crate::types::{Color, Ray, Scalar};
+
+/// Spacing in nanometers of wavelengths summed over when computing the color of a film
+const FILM_STEP: Scalar = 20.0;
+
+/// Reflectance of a thin film of index n2 between media of index n1 and n3, for light at a
+/// wavelength in nanometers arriving from the first medium at an angle with the given cosine,
+/// from the Airy summation of light reflected back and forth within the film
+fn airy(
+    cos1: Scalar,
+    n1: Scalar,
+    n2: Scalar,
+    n3: Scalar,
+    thickness: Scalar,
+    lambda: Scalar,
+) -> Scalar {
+    let sin2_1 = 1.0 - cos1 * cos1;
+    let sin2_2 = (n1 / n2).powi(2) * sin2_1;
+    let sin2_3 = (n1 / n3).powi(2) * sin2_1;
+    if sin2_2 >= 1.0 || sin2_3 >= 1.0 {
+        return 1.0;
+    }
+    let (cos2, cos3) = ((1.0 - sin2_2).sqrt(), (1.0 - sin2_3).sqrt());
+
+    // Phase difference between light reflected off of the top and bottom of the film
+    let delta = 4.0 * PI * n2 * thickness * cos2 / lambda;
+
+    let combine = |r12: Scalar, r23: Scalar| {
+        let cross = 2.0 * r12 * r23 * delta.cos();
+        (r12 * r12 + r23 * r23 + cross) / (1.0 + r12 * r12 * r23 * r23 + cross)
+    };
+    let rs = combine(
+        (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2),
+        (n2 * cos2 - n3 * cos3) / (n2 * cos2 + n3 * cos3),
+    );
+    let rp = combine(
+        (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2),
+        (n3 * cos2 - n2 * cos3) / (n3 * cos2 + n2 * cos3),
+    );
+
+    0.5 * (rs + rp)
+}
+
+/// Thin transparent film, such as a soap bubble or a slick of oil, whose reflections take on
+/// iridescent colors from interference between light reflected off of either side. The film
+/// lies over a substrate of the given index of refraction, or over air as in a bubble.
+#[derive(Debug, Clone)]
+pub struct ThinFilm {
+    /// Thickness of the film in nanometers
+    pub thickness: Arc<dyn Texture>,
+    pub film_ior: Scalar,
+    pub ior: Scalar,
+}
+
+impl ThinFilm {
+    /// Reflectance of the film, at the wavelength of the ray if it carries one and otherwise
+    /// integrated over the visible spectrum into RGB
+    fn reflectance(&self, ray: &Ray, cos1: Scalar, n1: Scalar, n3: Scalar, d: Scalar) -> Color {
+        if let Some(lambda) = ray.wavelength {
+            let r = airy(cos1, n1, self.film_ior, n3, d, lambda);
+            return Color::new(r, r, r, 1.0);
+        }
+
+        let (mut reflected, mut white) = ([0.0; 3], [0.0; 3]);
+        let mut lambda = LAMBDA_MIN;
+        while lambda <= LAMBDA_MAX {
+            let r = airy(cos1, n1, self.film_ior, n3, d, lambda);
+            let (x, y, z) = cie_xyz(lambda);
+            for (i, c) in [x, y, z].iter().enumerate() {
+                reflected[i] += r * c;
+                white[i] += c;
+            }
+            lambda += FILM_STEP;
+        }
+
+        let rgb = xyz_to_rgb(reflected[0], reflected[1], reflected[2]);
+        let white = xyz_to_rgb(white[0], white[1], white[2]);
+        Color::new(
+            (rgb.r / white.r).clamp(0.0, 1.0),
+            (rgb.g / white.g).clamp(0.0, 1.0),
+            (rgb.b / white.b).clamp(0.0, 1.0),
+            1.0,
+        )
+    }
+}
+
+impl Material for ThinFilm {
+    /// Calculate scattered ray generated by an incoming ray interacting with this surface
+    fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
+        let wo = -ray.direction;
+        let (n, n1, n3) = if wo.dot(&hit.normal) >= 0.0 {
+            (hit.normal, 1.0, self.ior)
+        } else {
+            (-hit.normal, self.ior, 1.0)
+        };
+
+        let cos1 = wo.dot(&n).min(1.0);
+        let d = self.thickness.scalar(&hit.uv, &hit.p);
+        let reflectance = self.reflectance(ray, cos1, n1, n3, d);
+
+        // Choose between reflection and transmission by average reflectance, tinting each by
+        // how far the reflectance of each channel differs from it
+        let p = ((reflectance.r + reflectance.g + reflectance.b) / 3.0).clamp(1e-3, 1.0 - 1e-3);
+        let white = Color::new(1.0, 1.0, 1.0, 1.0);
+        let transmitted = refract(&wo, &n, n3 / n1);
+        let (direction, attenuation, kind) = match transmitted {
+            Some(direction) if random::<Scalar>() >= p => (
+                direction,
+                (white - reflectance) / (1.0 - p),
+                ScatterKind::Transmission,
+            ),
+            Some(_) => (reflect(&wo, &n), reflectance / p, ScatterKind::Specular),
+            None => (reflect(&wo, &n), white, ScatterKind::Specular),
+        };
+
+        Some(ScatteredRay {
+            ray: Ray::new(hit.p, direction),
+            attenuation: Color::new(attenuation.r, attenuation.g, attenuation.b, 1.0),
+            kind,
+        })
+    }
+}
+
+/// Most interactions followed within a layered material before letting the path leave it
+const MAX_LAYER_BOUNCES: u32 = 16;
+
+/// Material coating a base material with another, such as a clear lacquer over paint or wood.
+/// Light refracted through the coat bounces between it and the base until it escapes, passing
+/// through an optionally absorbing layer of the given thickness on each trip between the two.
+/// The layer can only be sampled, not evaluated, so light reaches it only through scatter().
+#[derive(Debug, Clone)]
+pub struct Layered {
+    pub coat: Arc<dyn Material>,
+    pub base: Arc<dyn Material>,
+    pub thickness: Scalar,
+    pub absorption: Color,
+}
+
+impl Material for Layered {
+    /// Calculate scattered ray generated by an incoming ray interacting with this surface
+    fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
+        // Layers are infinitesimally close to the surface, so nothing absorbs on the way to them
+        let mut layer = hit.clone();
+        layer.t = 0.0;
+
+        let mut scattered = self.coat.scatter(ray, &layer)?;
+        let mut attenuation = scattered.attenuation;
+        let mut at_base = false;
+
+        // Paths reflected straight off of the coat take its kind, and any others that of the
+        // base beneath, unless scattered diffusely anywhere within the layer
+        let mut kind = scattered.kind;
+
+        for _ in 0..MAX_LAYER_BOUNCES {
+            let mut inner = scattered.ray;
+            inner.wavelength = ray.wavelength;
+
+            // Light heading up from the coat or down from the base leaves the material, while
+            // anything else crosses the layer to meet the other side
+            let cosine = inner.direction.dot(&hit.normal);
+            if (cosine > 0.0) != at_base {
+                return Some(ScatteredRay {
+                    ray: inner,
+                    attenuation,
+                    kind,
+                });
+            }
+
+            attenuation *= beer_lambert(&self.absorption, self.thickness / cosine.abs().max(1e-3));
+            scattered = if at_base {
+                self.coat.scatter(&inner, &layer)?
+            } else {
+                self.base.scatter(&inner, &layer)?
+            };
+            attenuation *= scattered.attenuation;
+            if kind != ScatterKind::Diffuse && (!at_base || scattered.kind == ScatterKind::Diffuse)
+            {
+                kind = scattered.kind;
+            }
+            at_base = !at_base;
+        }
+
+        // Rather than losing the energy of paths still within the layer, let the last of them
+        // carry on as though it had left
+        let mut inner = scattered.ray;
+        inner.wavelength = ray.wavelength;
+        Some(ScatteredRay {
+            ray: inner,
+            attenuation,
+            kind,
+        })
+    }
+
+    /// Color of the surface apart from lighting, guiding the denoiser
+    fn albedo(&self, hit: &HitResult) -> Color {
+        self.base.albedo(hit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materials::{Dialectric, Lambertian};
+    use crate::textures::Constant;
+    use crate::types::{Point2, Point3, Vector3};
+
+    #[test]
+    fn coated_diffuse_scatters_as_the_coat_or_diffusely() {
+        let material = Arc::new(Layered {
+            coat: Arc::new(Dialectric {
+                albedo: Arc::new(Constant {
+                    color: Color::new(1.0, 1.0, 1.0, 1.0),
+                }),
+                ior: Arc::new(Constant::scalar(1.5)),
+                dispersion: None,
+            }),
+            base: Arc::new(Lambertian {
+                albedo: Arc::new(Constant {
+                    color: Color::new(0.8, 0.8, 0.8, 1.0),
+                }),
+            }),
+            thickness: 0.0,
+            absorption: Color::new(0.0, 0.0, 0.0, 1.0),
+        });
+        let hit = HitResult {
+            t: 1.0,
+            p: Point3::origin(),
+            normal: Vector3::y(),
+            uv: Point2::new(0.5, 0.5),
+            tangent: Vector3::x(),
+            bitangent: -Vector3::z(),
+            material: material.clone(),
+        };
+        let ray = Ray::new(Point3::new(-1.0, 1.0, 0.0), Vector3::new(1.0, -1.0, 0.0));
+
+        let kinds: Vec<_> = (0..1000)
+            .filter_map(|_| material.scatter(&ray, &hit))
+            .map(|scattered| scattered.kind)
+            .collect();
+        assert!(kinds.contains(&ScatterKind::Specular));
+        assert!(kinds.contains(&ScatterKind::Diffuse));
+        assert!(!kinds.contains(&ScatterKind::Transmission));
+    }
+}
@@ -0,0 +1,142 @@
+use scoped_threadpool::Pool;
+
+use crate::framebuffer::Framebuffer;
+use crate::types::{Color, Scalar, Vector3};
+
+/// Weights of the B3 spline kernel along each axis, applied with growing gaps between taps
+const KERNEL: [Scalar; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Smallest albedo divided out of a pixel, keeping black surfaces from blowing up
+const MIN_ALBEDO: Scalar = 0.01;
+
+/// Squared distance between the red, green, and blue components of two colors
+fn distance2(a: &Color, b: &Color) -> Scalar {
+    let d = *a - *b;
+    d.r * d.r + d.g * d.g + d.b * d.b
+}
+
+/// Edge avoiding à-trous wavelet filter, smoothing the noise of a rendered image by repeatedly
+/// blurring it with a wider kernel while keeping apart neighbouring pixels that differ in color,
+/// normal, or albedo. Lighting is filtered separately from the albedo of the surfaces it falls
+/// on, so that textures stay sharp.
+#[derive(Debug, Clone)]
+pub struct Denoiser {
+    /// Number of passes, each doubling the gap between taps of the kernel
+    pub iterations: u32,
+    /// Difference in lighting over which pixels are kept apart, halved with each pass
+    pub color_sigma: Scalar,
+    /// Difference in normal over which pixels are kept apart
+    pub normal_sigma: Scalar,
+    /// Difference in albedo over which pixels are kept apart
+    pub albedo_sigma: Scalar,
+}
+
+impl Denoiser {
+    /// Denoise the image held by a framebuffer, returning its pixels in rows from the top
+    pub fn denoise(&self, framebuffer: &Framebuffer) -> Vec<Color> {
+        let width = framebuffer.width() as usize;
+        let height = framebuffer.height() as usize;
+
+        let mut albedo = Vec::with_capacity(width * height);
+        let mut normal = Vec::with_capacity(width * height);
+        let mut alpha = Vec::with_capacity(width * height);
+        let mut lighting = Vec::with_capacity(width * height);
+        for y in 0..height as u32 {
+            for x in 0..width as u32 {
+                let a = framebuffer.albedo(x, y);
+                let a = Color::new(
+                    a.r.max(MIN_ALBEDO),
+                    a.g.max(MIN_ALBEDO),
+                    a.b.max(MIN_ALBEDO),
+                    1.0,
+                );
+                let c = framebuffer.pixel(x, y);
+                albedo.push(a);
+                normal.push(framebuffer.normal(x, y));
+                alpha.push(c.a);
+                lighting.push(c / a);
+            }
+        }
+
+        let nproc = num_cpus::get();
+        let mut pool = Pool::new(nproc as u32);
+
+        for i in 0..self.iterations {
+            let step = 1 << i;
+            let color_sigma = self.color_sigma / (1 << i) as Scalar;
+            let mut filtered = vec![Color::new(0.0, 0.0, 0.0, 1.0); width * height];
+
+            pool.scoped(|scoped| {
+                let (lighting, albedo, normal) = (&lighting, &albedo, &normal);
+                for (y, row) in filtered.chunks_mut(width).enumerate() {
+                    scoped.execute(move || {
+                        for (x, out) in row.iter_mut().enumerate() {
+                            *out = self.filter_pixel(
+                                (x, y),
+                                (width, height),
+                                step,
+                                color_sigma,
+                                (lighting, albedo, normal),
+                            );
+                        }
+                    });
+                }
+            });
+
+            lighting = filtered;
+        }
+
+        lighting
+            .iter()
+            .zip(albedo.iter())
+            .zip(alpha.iter())
+            .map(|((l, a), &alpha)| Color {
+                a: alpha,
+                ..*l * *a
+            })
+            .collect()
+    }
+
+    /// Filter a single pixel with a pass of the kernel at a given gap between taps
+    fn filter_pixel(
+        &self,
+        (x, y): (usize, usize),
+        (width, height): (usize, usize),
+        step: usize,
+        color_sigma: Scalar,
+        (lighting, albedo, normal): (&[Color], &[Color], &[Vector3]),
+    ) -> Color {
+        let p = y * width + x;
+        let mut sum = Color::new(0.0, 0.0, 0.0, 1.0);
+        let mut total = 0.0;
+
+        for (j, ky) in KERNEL.iter().enumerate() {
+            let qy = y as isize + (j as isize - 2) * step as isize;
+            if qy < 0 || qy >= height as isize {
+                continue;
+            }
+            for (i, kx) in KERNEL.iter().enumerate() {
+                let qx = x as isize + (i as isize - 2) * step as isize;
+                if qx < 0 || qx >= width as isize {
+                    continue;
+                }
+
+                let q = qy as usize * width + qx as usize;
+                let wc =
+                    (-distance2(&lighting[p], &lighting[q]) / (color_sigma * color_sigma)).exp();
+                let wn = (-(normal[p] - normal[q]).magnitude_squared()
+                    / (self.normal_sigma * self.normal_sigma))
+                    .exp();
+                let wa = (-distance2(&albedo[p], &albedo[q])
+                    / (self.albedo_sigma * self.albedo_sigma))
+                    .exp();
+                let weight = kx * ky * wc * wn * wa;
+
+                sum += lighting[q] * weight;
+                total += weight;
+            }
+        }
+
+        sum / total
+    }
+}
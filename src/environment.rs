@@ -0,0 +1,523 @@
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use failure::Error;
+use image::hdr::HDRDecoder;
+use rand::random;
+
+use crate::spectrum::xyz_to_rgb;
+use crate::types::{basis, Color, Scalar, Vector3};
+
+/// Environment defines the light arriving from infinitely far away along rays which escape
+/// the scene
+pub trait Environment: Send + Sync + std::fmt::Debug {
+    /// Radiance arriving from the given direction
+    fn color(&self, direction: &Vector3) -> Color;
+
+    /// Sample a direction toward the environment in proportion to its brightness, returning the
+    /// direction, radiance from that direction and the probability density of choosing it
+    fn sample(&self) -> Option<(Vector3, Color, Scalar)> {
+        None
+    }
+
+    /// Probability density with which sample() would have chosen the given direction
+    fn pdf(&self, direction: &Vector3) -> Scalar {
+        let _ = direction;
+        0.0
+    }
+}
+
+/// Sky blending linearly between two colors from the horizon to the zenith
+#[derive(Debug, Clone)]
+pub struct Gradient {
+    pub horizon: Color,
+    pub zenith: Color,
+}
+
+impl Environment for Gradient {
+    /// Radiance arriving from the given direction
+    fn color(&self, direction: &Vector3) -> Color {
+        let unit_direction = direction.normalize();
+        let t = 0.5 * (unit_direction.y + 1.0);
+        self.horizon * (1.0 - t) + self.zenith * t
+    }
+}
+
+/// Search a cumulative distribution for the bucket containing a uniform random value
+fn search(cdf: &[Scalar], u: Scalar) -> usize {
+    let i = cdf.partition_point(|&c| c <= u);
+    i.clamp(1, cdf.len() - 1) - 1
+}
+
+/// Build a normalized cumulative distribution over a row of weights, returning it and its total
+fn cumulative(weights: &[Scalar]) -> (Vec<Scalar>, Scalar) {
+    let mut cdf = Vec::with_capacity(weights.len() + 1);
+    let mut total = 0.0;
+    cdf.push(0.0);
+    for w in weights {
+        total += w;
+        cdf.push(total);
+    }
+
+    if total > 0.0 {
+        for c in cdf.iter_mut() {
+            *c /= total;
+        }
+    } else {
+        // Fall back to a uniform distribution over a black row
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = i as Scalar / weights.len() as Scalar;
+        }
+    }
+
+    (cdf, total)
+}
+
+/// Equirectangular environment map, importance sampled according to the brightness of its pixels
+#[derive(Debug, Clone)]
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    rotation: Scalar,
+    intensity: Scalar,
+    marginal: Vec<Scalar>,
+    conditional: Vec<Vec<Scalar>>,
+    weights: Vec<Scalar>,
+    total: Scalar,
+}
+
+impl EnvironmentMap {
+    /// Create an environment map from rows of pixels, rotated about the vertical axis by the
+    /// given angle in degrees and scaled in brightness by an intensity
+    pub fn new(
+        width: usize,
+        height: usize,
+        pixels: Vec<Color>,
+        rotation_degrees: Scalar,
+        intensity: Scalar,
+    ) -> Self {
+        let mut conditional = Vec::with_capacity(height);
+        let mut row_weights = Vec::with_capacity(height);
+        let mut weights = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            // Rows nearer the poles cover less solid angle
+            let sin_theta = (PI * (y as Scalar + 0.5) / height as Scalar).sin();
+            let row: Vec<Scalar> = pixels[y * width..(y + 1) * width]
+                .iter()
+                .map(|c| (0.2126 * c.r + 0.7152 * c.g + 0.0722 * c.b).max(0.0) * sin_theta)
+                .collect();
+
+            let (cdf, total) = cumulative(&row);
+            weights.extend(row);
+            conditional.push(cdf);
+            row_weights.push(total);
+        }
+
+        let (marginal, total) = cumulative(&row_weights);
+
+        Self {
+            width,
+            height,
+            pixels,
+            rotation: rotation_degrees * PI / 180.0,
+            intensity,
+            marginal,
+            conditional,
+            weights,
+            total,
+        }
+    }
+
+    /// Load an environment map from an image file, preserving high dynamic range in Radiance
+    /// HDR files
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        rotation_degrees: Scalar,
+        intensity: Scalar,
+    ) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let is_hdr = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("hdr"));
+
+        let (width, height, pixels) = if is_hdr {
+            let decoder = HDRDecoder::new(BufReader::new(File::open(path)?))?;
+            let metadata = decoder.metadata();
+            let pixels = decoder
+                .read_image_hdr()?
+                .iter()
+                .map(|p| Color::new(p.data[0], p.data[1], p.data[2], 1.0))
+                .collect();
+            (metadata.width as usize, metadata.height as usize, pixels)
+        } else {
+            let image = image::open(path)?.to_rgb();
+            let (width, height) = image.dimensions();
+            let pixels = image.pixels().map(|p| Color::from(*p)).collect();
+            (width as usize, height as usize, pixels)
+        };
+
+        Ok(Self::new(
+            width,
+            height,
+            pixels,
+            rotation_degrees,
+            intensity,
+        ))
+    }
+
+    /// Map a direction to continuous image coordinates between 0.0 and 1.0
+    fn direction_to_uv(&self, direction: &Vector3) -> (Scalar, Scalar) {
+        let d = direction.normalize();
+        let phi = d.x.atan2(-d.z) - self.rotation;
+        let u = phi / (2.0 * PI) + 0.5;
+        let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+        (u - u.floor(), v)
+    }
+
+    /// Map continuous image coordinates to a direction
+    fn uv_to_direction(&self, u: Scalar, v: Scalar) -> Vector3 {
+        let phi = (u - 0.5) * 2.0 * PI + self.rotation;
+        let theta = v * PI;
+        Vector3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+
+    /// Index of the pixel containing the given image coordinates
+    fn pixel(&self, u: Scalar, v: Scalar) -> usize {
+        let x = ((u * self.width as Scalar) as usize).min(self.width - 1);
+        let y = ((v * self.height as Scalar) as usize).min(self.height - 1);
+        y * self.width + x
+    }
+}
+
+impl Environment for EnvironmentMap {
+    /// Radiance arriving from the given direction
+    fn color(&self, direction: &Vector3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        self.pixels[self.pixel(u, v)] * self.intensity
+    }
+
+    /// Sample a direction toward the environment in proportion to its brightness, returning the
+    /// direction, radiance from that direction and the probability density of choosing it
+    fn sample(&self) -> Option<(Vector3, Color, Scalar)> {
+        if self.total <= 0.0 {
+            return None;
+        }
+
+        let y = search(&self.marginal, random::<Scalar>());
+        let x = search(&self.conditional[y], random::<Scalar>());
+
+        let u = (x as Scalar + random::<Scalar>()) / self.width as Scalar;
+        let v = (y as Scalar + random::<Scalar>()) / self.height as Scalar;
+        let direction = self.uv_to_direction(u, v);
+
+        let pdf = self.pdf(&direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some((
+            direction,
+            self.pixels[y * self.width + x] * self.intensity,
+            pdf,
+        ))
+    }
+
+    /// Probability density with which sample() would have chosen the given direction
+    fn pdf(&self, direction: &Vector3) -> Scalar {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 || self.total <= 0.0 {
+            return 0.0;
+        }
+
+        // Convert the density over pixels into a density over solid angle
+        let pixel_pdf = self.weights[self.pixel(u, v)] / self.total;
+        pixel_pdf * (self.width * self.height) as Scalar / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+/// Perez sky luminance distribution for a view direction at angle theta from the zenith and
+/// gamma from the sun
+fn perez(c: &[Scalar; 5], cos_theta: Scalar, gamma: Scalar) -> Scalar {
+    (1.0 + c[0] * (c[1] / cos_theta).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos() * gamma.cos())
+}
+
+/// Angular radius of the sun as seen from the earth, in radians
+const SUN_ANGULAR_RADIUS: Scalar = 0.004_65;
+
+/// Luminance of the sun at the top of the atmosphere relative to the sky model, in kcd/m^2
+const SUN_LUMINANCE: Scalar = 1.6e6;
+
+/// Scale from the luminance units of the sky model to scene radiance at unit intensity
+const SKY_SCALE: Scalar = 0.05;
+
+/// Analytic daylight sky after Preetham, Shirley and Smits, "A Practical Analytic Model for
+/// Daylight" (1999), with a disk for the sun itself
+#[derive(Debug, Clone)]
+pub struct PhysicalSky {
+    sun: Vector3,
+    theta_sun: Scalar,
+    coefficients: [[Scalar; 5]; 3],
+    zenith: [Scalar; 3],
+    sun_radiance: Color,
+    intensity: Scalar,
+}
+
+impl PhysicalSky {
+    /// Create a sky for a sun at the given elevation above the horizon and azimuth clockwise
+    /// from -z toward +x, both in degrees, and for a given atmospheric turbidity between 2.0
+    /// (clear) and 10.0 (hazy)
+    pub fn new(
+        elevation_degrees: Scalar,
+        azimuth_degrees: Scalar,
+        turbidity: Scalar,
+        intensity: Scalar,
+    ) -> Self {
+        // The model breaks down once the sun drops below the horizon
+        let elevation = elevation_degrees.clamp(0.5, 90.0) * PI / 180.0;
+        let azimuth = azimuth_degrees * PI / 180.0;
+        let sun = Vector3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+
+        let t = turbidity;
+        let coefficients = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let theta_sun = PI / 2.0 - elevation;
+        let (t1, t2, t3) = (theta_sun, theta_sun * theta_sun, theta_sun.powi(3));
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith = [
+            (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192,
+            t * t * (0.00166 * t3 - 0.00375 * t2 + 0.00209 * t1)
+                + t * (-0.02903 * t3 + 0.06377 * t2 - 0.03202 * t1 + 0.00394)
+                + (0.11693 * t3 - 0.21196 * t2 + 0.06052 * t1 + 0.25886),
+            t * t * (0.00275 * t3 - 0.00610 * t2 + 0.00317 * t1)
+                + t * (-0.04214 * t3 + 0.08970 * t2 - 0.04153 * t1 + 0.00516)
+                + (0.15346 * t3 - 0.26756 * t2 + 0.06670 * t1 + 0.26688),
+        ];
+
+        // Attenuate sunlight by Rayleigh and aerosol scattering along its path through the air,
+        // evaluated at representative wavelengths for each channel in micrometers
+        let air_mass =
+            1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_sun * 180.0 / PI).powf(-1.253));
+        let beta = 0.046_08 * t - 0.045_86;
+        let transmittance = |lambda: Scalar| {
+            let rayleigh = 0.008_735 * lambda.powf(-4.08);
+            let aerosol = beta * lambda.powf(-1.3);
+            (-air_mass * (rayleigh + aerosol)).exp()
+        };
+        let sun_radiance = Color::new(
+            transmittance(0.68),
+            transmittance(0.55),
+            transmittance(0.44),
+            1.0,
+        ) * SUN_LUMINANCE;
+
+        Self {
+            sun,
+            theta_sun,
+            coefficients,
+            zenith,
+            sun_radiance,
+            intensity,
+        }
+    }
+
+    /// Radiance of the sky alone, excluding the sun, from the given direction
+    fn sky(&self, direction: &Vector3) -> Color {
+        // Directions below the horizon see the sky as it appears at the horizon
+        let cos_theta = direction.y.max(0.01);
+        let gamma = direction.dot(&self.sun).clamp(-1.0, 1.0).acos();
+
+        let value = |i: usize| {
+            let c = &self.coefficients[i];
+            self.zenith[i] * perez(c, cos_theta, gamma)
+                / perez(c, 1.0, self.theta_sun).max(Scalar::EPSILON)
+        };
+
+        let (luminance, x, y) = (value(0), value(1), value(2));
+        if y <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0, 1.0);
+        }
+
+        let rgb = xyz_to_rgb(x * luminance / y, luminance, (1.0 - x - y) * luminance / y);
+        Color::new(rgb.r.max(0.0), rgb.g.max(0.0), rgb.b.max(0.0), 1.0) * SKY_SCALE
+    }
+
+    /// Probability of sampling the sun rather than the sky
+    fn sun_probability(&self) -> Scalar {
+        if self.sun.y > 0.0 {
+            0.5
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Environment for PhysicalSky {
+    /// Radiance arriving from the given direction
+    fn color(&self, direction: &Vector3) -> Color {
+        let d = direction.normalize();
+        let mut c = self.sky(&d);
+        if d.dot(&self.sun) >= SUN_ANGULAR_RADIUS.cos() {
+            c += self.sun_radiance * SKY_SCALE;
+        }
+
+        c * self.intensity
+    }
+
+    /// Sample a direction toward the environment in proportion to its brightness, returning the
+    /// direction, radiance from that direction and the probability density of choosing it
+    fn sample(&self) -> Option<(Vector3, Color, Scalar)> {
+        let direction = if random::<Scalar>() < self.sun_probability() {
+            // Uniformly sample the cone subtended by the sun
+            let cos_max = SUN_ANGULAR_RADIUS.cos();
+            let cos_theta = 1.0 - random::<Scalar>() * (1.0 - cos_max);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * PI * random::<Scalar>();
+            let (u, v) = basis(&self.sun);
+            sin_theta * phi.cos() * u + sin_theta * phi.sin() * v + cos_theta * self.sun
+        } else {
+            // Cosine weighted sampling of the upper hemisphere of sky
+            let r = random::<Scalar>().sqrt();
+            let phi = 2.0 * PI * random::<Scalar>();
+            Vector3::new(r * phi.cos(), (1.0 - r * r).max(0.0).sqrt(), r * phi.sin())
+        };
+
+        let pdf = self.pdf(&direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some((direction, self.color(&direction), pdf))
+    }
+
+    /// Probability density with which sample() would have chosen the given direction
+    fn pdf(&self, direction: &Vector3) -> Scalar {
+        let d = direction.normalize();
+        let p_sun = self.sun_probability();
+        let cos_max = SUN_ANGULAR_RADIUS.cos();
+
+        let sun = if d.dot(&self.sun) >= cos_max {
+            1.0 / (2.0 * PI * (1.0 - cos_max))
+        } else {
+            0.0
+        };
+        let sky = d.y.max(0.0) / PI;
+
+        p_sun * sun + (1.0 - p_sun) * sky
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Small map with a bright pixel in an otherwise dim sky
+    fn map() -> EnvironmentMap {
+        let (width, height) = (8, 4);
+        let mut pixels = vec![Color::new(0.1, 0.2, 0.3, 1.0); width * height];
+        pixels[width + 5] = Color::new(20.0, 20.0, 20.0, 1.0);
+        EnvironmentMap::new(width, height, pixels, 30.0, 1.0)
+    }
+
+    #[test]
+    fn cumulative_distributions_are_normalized() {
+        let (cdf, total) = cumulative(&[1.0, 3.0]);
+        assert_eq!(total, 4.0);
+        assert_eq!(cdf, vec![0.0, 0.25, 1.0]);
+        assert_eq!(search(&cdf, 0.0), 0);
+        assert_eq!(search(&cdf, 0.2), 0);
+        assert_eq!(search(&cdf, 0.25), 1);
+        assert_eq!(search(&cdf, 0.999), 1);
+
+        let (cdf, total) = cumulative(&[0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(total, 0.0);
+        assert_eq!(cdf, vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+    }
+
+    #[test]
+    fn pdf_integrates_to_one_over_the_sphere() {
+        let map = map();
+        let n = 512;
+        let mut integral = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let u = (i as Scalar + 0.5) / n as Scalar;
+                let v = (j as Scalar + 0.5) / n as Scalar;
+                let d = map.uv_to_direction(u, v);
+                // Solid angle of a cell of the grid over image coordinates
+                let area = 2.0 * PI * PI * (v * PI).sin() / (n * n) as Scalar;
+                integral += map.pdf(&d) * area;
+            }
+        }
+        assert!((integral - 1.0).abs() < 1e-2, "{}", integral);
+    }
+
+    #[test]
+    fn samples_agree_with_pdf_and_color() {
+        let map = map();
+        for (d, color, pdf) in (0..1000).filter_map(|_| map.sample()) {
+            assert!((pdf - map.pdf(&d)).abs() <= 1e-3 * pdf);
+            let expected = map.color(&d);
+            assert!((color.r - expected.r).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn samples_land_in_pixels_in_proportion_to_their_weight() {
+        let map = map();
+        let count = 200_000;
+        let mut histogram = vec![0usize; map.width * map.height];
+        // Directions at the very pole may be refused, where the density is undefined
+        for (d, _, _) in (0..count).filter_map(|_| map.sample()) {
+            let (u, v) = map.direction_to_uv(&d);
+            histogram[map.pixel(u, v)] += 1;
+        }
+        let count: usize = histogram.iter().sum();
+
+        for (i, &hits) in histogram.iter().enumerate() {
+            let expected = map.weights[i] / map.total;
+            let observed = hits as Scalar / count as Scalar;
+            assert!(
+                (observed - expected).abs() < 0.01,
+                "{} {}",
+                observed,
+                expected
+            );
+        }
+    }
+}
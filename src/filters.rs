@@ -0,0 +1,222 @@
+use std::f32::consts::PI;
+
+use crate::types::Scalar;
+
+/// Filter reconstructing the image from samples, weighting each by its offset in pixels from
+/// the center of the pixels it contributes to
+pub trait Filter: Send + Sync + std::fmt::Debug {
+    /// Distance from the center of a pixel beyond which samples are given no weight
+    fn radius(&self) -> Scalar;
+
+    /// Weight of a sample at the given offset from the center of a pixel
+    fn eval(&self, x: Scalar, y: Scalar) -> Scalar;
+}
+
+/// Filter weighting all samples within its radius equally, which for a radius of half a pixel
+/// averages the samples within each pixel
+#[derive(Debug, Clone)]
+pub struct BoxFilter {
+    pub radius: Scalar,
+}
+
+impl Filter for BoxFilter {
+    /// Distance from the center of a pixel beyond which samples are given no weight
+    fn radius(&self) -> Scalar {
+        self.radius
+    }
+
+    /// Weight of a sample at the given offset from the center of a pixel
+    fn eval(&self, x: Scalar, y: Scalar) -> Scalar {
+        if x.abs() <= self.radius && y.abs() <= self.radius {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Filter whose weight falls linearly from the center of the pixel to its radius
+#[derive(Debug, Clone)]
+pub struct TentFilter {
+    pub radius: Scalar,
+}
+
+impl Filter for TentFilter {
+    /// Distance from the center of a pixel beyond which samples are given no weight
+    fn radius(&self) -> Scalar {
+        self.radius
+    }
+
+    /// Weight of a sample at the given offset from the center of a pixel
+    fn eval(&self, x: Scalar, y: Scalar) -> Scalar {
+        (self.radius - x.abs()).max(0.0) * (self.radius - y.abs()).max(0.0)
+    }
+}
+
+/// Gaussian filter, offset so that it falls to zero at its radius, with larger values of alpha
+/// falling off more quickly and giving sharper images
+#[derive(Debug, Clone)]
+pub struct GaussianFilter {
+    pub radius: Scalar,
+    pub alpha: Scalar,
+}
+
+impl GaussianFilter {
+    /// Weight along a single axis
+    fn gaussian(&self, d: Scalar) -> Scalar {
+        ((-self.alpha * d * d).exp() - (-self.alpha * self.radius * self.radius).exp()).max(0.0)
+    }
+}
+
+impl Filter for GaussianFilter {
+    /// Distance from the center of a pixel beyond which samples are given no weight
+    fn radius(&self) -> Scalar {
+        self.radius
+    }
+
+    /// Weight of a sample at the given offset from the center of a pixel
+    fn eval(&self, x: Scalar, y: Scalar) -> Scalar {
+        self.gaussian(x) * self.gaussian(y)
+    }
+}
+
+/// Cubic filter of Mitchell and Netravali, trading blurring against ringing through its two
+/// parameters, whose recommended values are both a third
+#[derive(Debug, Clone)]
+pub struct MitchellFilter {
+    pub radius: Scalar,
+    pub b: Scalar,
+    pub c: Scalar,
+}
+
+impl MitchellFilter {
+    /// Weight along a single axis, for an offset scaled to run from -2.0 to 2.0 over the filter
+    fn mitchell(&self, d: Scalar) -> Scalar {
+        let (b, c) = (self.b, self.c);
+        let d = d.abs();
+        if d > 2.0 {
+            0.0
+        } else if d > 1.0 {
+            ((-b - 6.0 * c) * d * d * d
+                + (6.0 * b + 30.0 * c) * d * d
+                + (-12.0 * b - 48.0 * c) * d
+                + (8.0 * b + 24.0 * c))
+                / 6.0
+        } else {
+            ((12.0 - 9.0 * b - 6.0 * c) * d * d * d
+                + (-18.0 + 12.0 * b + 6.0 * c) * d * d
+                + (6.0 - 2.0 * b))
+                / 6.0
+        }
+    }
+}
+
+impl Filter for MitchellFilter {
+    /// Distance from the center of a pixel beyond which samples are given no weight
+    fn radius(&self) -> Scalar {
+        self.radius
+    }
+
+    /// Weight of a sample at the given offset from the center of a pixel
+    fn eval(&self, x: Scalar, y: Scalar) -> Scalar {
+        self.mitchell(2.0 * x / self.radius) * self.mitchell(2.0 * y / self.radius)
+    }
+}
+
+/// Sinc filter windowed by a wider sinc stretched over its radius, keeping images sharp at
+/// the cost of some ringing around edges
+#[derive(Debug, Clone)]
+pub struct LanczosFilter {
+    pub radius: Scalar,
+}
+
+impl LanczosFilter {
+    /// Weight along a single axis
+    fn lanczos(&self, d: Scalar) -> Scalar {
+        let sinc = |x: Scalar| {
+            if x.abs() < 1e-5 {
+                1.0
+            } else {
+                (PI * x).sin() / (PI * x)
+            }
+        };
+
+        if d.abs() > self.radius {
+            0.0
+        } else {
+            sinc(d) * sinc(d / self.radius)
+        }
+    }
+}
+
+impl Filter for LanczosFilter {
+    /// Distance from the center of a pixel beyond which samples are given no weight
+    fn radius(&self) -> Scalar {
+        self.radius
+    }
+
+    /// Weight of a sample at the given offset from the center of a pixel
+    fn eval(&self, x: Scalar, y: Scalar) -> Scalar {
+        self.lanczos(x) * self.lanczos(y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Scalar, b: Scalar) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn mitchell_matches_known_values() {
+        let filter = MitchellFilter {
+            radius: 2.0,
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        };
+        assert!(close(filter.mitchell(0.0), 8.0 / 9.0));
+        assert!(close(filter.mitchell(1.0), 1.0 / 18.0));
+        assert!(close(filter.mitchell(-1.0), 1.0 / 18.0));
+        assert!(close(filter.mitchell(2.0), 0.0));
+        assert!(close(filter.eval(0.0, 0.0), 64.0 / 81.0));
+        assert!(close(filter.eval(1.0, 0.0), 8.0 / 9.0 / 18.0));
+        assert_eq!(filter.eval(2.5, 0.0), 0.0);
+    }
+
+    #[test]
+    fn lanczos_matches_known_values() {
+        let filter = LanczosFilter { radius: 2.0 };
+        assert!(close(filter.eval(0.0, 0.0), 1.0));
+        assert!(close(filter.lanczos(1.0), 0.0));
+        assert!(close(filter.lanczos(0.5), 0.573_159));
+        assert!(close(filter.lanczos(-0.5), 0.573_159));
+        assert_eq!(filter.lanczos(2.5), 0.0);
+    }
+
+    #[test]
+    fn gaussian_falls_to_zero_at_its_radius() {
+        let filter = GaussianFilter {
+            radius: 1.5,
+            alpha: 2.0,
+        };
+        assert!(close(filter.eval(1.5, 0.0), 0.0));
+        assert!(close(filter.eval(0.0, -1.5), 0.0));
+        assert_eq!(filter.eval(2.0, 0.0), 0.0);
+        assert!(filter.eval(0.0, 0.0) > filter.eval(0.5, 0.0));
+        assert!(filter.eval(0.5, 0.0) > 0.0);
+    }
+
+    #[test]
+    fn box_and_tent_cover_their_radius() {
+        let square = BoxFilter { radius: 0.5 };
+        assert_eq!(square.eval(0.5, -0.5), 1.0);
+        assert_eq!(square.eval(0.6, 0.0), 0.0);
+
+        let tent = TentFilter { radius: 1.0 };
+        assert!(close(tent.eval(0.0, 0.0), 1.0));
+        assert!(close(tent.eval(0.5, 0.5), 0.25));
+        assert_eq!(tent.eval(1.0, 0.0), 0.0);
+    }
+}
@@ -0,0 +1,243 @@
+use std::sync::{Arc, Mutex};
+
+use scoped_threadpool::Pool;
+
+use crate::filters::Filter;
+use crate::types::{Color, Scalar, Vector3};
+
+/// Sample of the image at a point given in pixels from its top left corner, with an alpha of
+/// 1.0 where the sample covers something and 0.0 where it sees the background
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub x: Scalar,
+    pub y: Scalar,
+    pub color: Color,
+    /// Albedo of the surface first seen by the sample, used to guide denoising
+    pub albedo: Color,
+    /// Normal of the surface first seen by the sample, used to guide denoising
+    pub normal: Vector3,
+}
+
+/// Filter weighted sums of the samples splatted into a pixel
+#[derive(Debug, Clone, Copy)]
+struct Accumulator {
+    color: Color,
+    /// Total weight of the samples included in the color
+    weight: Scalar,
+    /// Total weight of the samples covering the pixel
+    coverage: Scalar,
+    /// Total weight of all samples
+    total: Scalar,
+    albedo: Color,
+    normal: Vector3,
+}
+
+impl Accumulator {
+    /// Accumulator holding no samples
+    fn empty() -> Self {
+        Self {
+            color: Color::new(0.0, 0.0, 0.0, 1.0),
+            weight: 0.0,
+            coverage: 0.0,
+            total: 0.0,
+            albedo: Color::new(0.0, 0.0, 0.0, 1.0),
+            normal: Vector3::zeros(),
+        }
+    }
+
+    /// Combine the samples held by another accumulator into this one
+    fn merge(&mut self, other: &Accumulator) {
+        self.color += other.color;
+        self.weight += other.weight;
+        self.coverage += other.coverage;
+        self.total += other.total;
+        self.albedo += other.albedo;
+        self.normal += other.normal;
+    }
+}
+
+/// Floating point image into which samples are splatted, weighted by a reconstruction filter,
+/// across every pixel within the radius of the filter
+#[derive(Debug)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    filter: Arc<dyn Filter>,
+    /// Whether the background is left transparent, in which case the color of a pixel is that
+    /// of the samples covering it alone
+    transparent: bool,
+    /// Number of standard deviations above the other samples of its pixel at which the
+    /// brightness of a sample is limited, if outliers are rejected
+    outlier_threshold: Option<Scalar>,
+    rows: Vec<Mutex<Vec<Accumulator>>>,
+}
+
+impl Framebuffer {
+    /// Create an empty framebuffer
+    pub fn new(
+        width: u32,
+        height: u32,
+        filter: Arc<dyn Filter>,
+        transparent: bool,
+        outlier_threshold: Option<Scalar>,
+    ) -> Self {
+        Self {
+            width,
+            height,
+            filter,
+            transparent,
+            outlier_threshold,
+            rows: (0..height)
+                .map(|_| Mutex::new(vec![Accumulator::empty(); width as usize]))
+                .collect(),
+        }
+    }
+
+    /// Render the image in parallel rows, using a function to generate the samples of each
+    /// pixel and splatting them into the pixels around them
+    pub fn render<F>(&self, f: F)
+    where
+        F: Fn(u32, u32) -> Vec<Sample> + Sync,
+    {
+        let nproc = num_cpus::get();
+        let mut pool = Pool::new(nproc as u32);
+
+        pool.scoped(|scoped| {
+            for y in 0..self.height {
+                let f = &f;
+                scoped.execute(move || {
+                    let samples: Vec<Sample> = (0..self.width)
+                        .flat_map(|x| self.reject_outliers(f(x, y)))
+                        .collect();
+                    self.splat_row(y, &samples);
+                })
+            }
+        });
+    }
+
+    /// Scale back samples of a pixel whose luminance lies too far above the mean of the others,
+    /// measured in standard deviations of the others, to the luminance at that threshold
+    fn reject_outliers(&self, mut samples: Vec<Sample>) -> Vec<Sample> {
+        let threshold = match self.outlier_threshold {
+            Some(threshold) if samples.len() > 2 => threshold,
+            _ => return samples,
+        };
+
+        let luminance: Vec<Scalar> = samples.iter().map(|s| s.color.luminance()).collect();
+        let sum: Scalar = luminance.iter().sum();
+        let sum2: Scalar = luminance.iter().map(|l| l * l).sum();
+        let n = (samples.len() - 1) as Scalar;
+
+        for (sample, &l) in samples.iter_mut().zip(luminance.iter()) {
+            let mean = (sum - l) / n;
+            let variance = ((sum2 - l * l) / n - mean * mean).max(0.0);
+            let limit = mean + threshold * variance.sqrt();
+            if l > limit {
+                sample.color *= limit / l;
+            }
+        }
+
+        samples
+    }
+
+    /// Splat the samples of a row of pixels, accumulating them locally before adding them to
+    /// the shared rows they reach so that each is locked only once
+    fn splat_row(&self, y: u32, samples: &[Sample]) {
+        let radius = self.filter.radius();
+        let first = (y as Scalar - 0.5 - radius).ceil().max(0.0) as u32;
+        let last = ((y as Scalar + 0.5 + radius).floor() as u32).min(self.height - 1);
+        let mut local =
+            vec![vec![Accumulator::empty(); self.width as usize]; (last - first + 1) as usize];
+
+        for sample in samples {
+            // Pixels whose centers lie within the radius of the sample
+            let x0 = (sample.x - 0.5 - radius).ceil().max(0.0) as u32;
+            let x1 = ((sample.x - 0.5 + radius).floor().max(0.0) as u32).min(self.width - 1);
+            let y0 = (sample.y - 0.5 - radius).ceil().max(first as Scalar) as u32;
+            let y1 = ((sample.y - 0.5 + radius).floor().max(0.0) as u32).min(last);
+
+            let included = if self.transparent {
+                sample.color.a
+            } else {
+                1.0
+            };
+            for py in y0..=y1 {
+                for px in x0..=x1 {
+                    let weight = self.filter.eval(
+                        sample.x - (px as Scalar + 0.5),
+                        sample.y - (py as Scalar + 0.5),
+                    );
+                    if weight == 0.0 {
+                        continue;
+                    }
+
+                    let pixel = &mut local[(py - first) as usize][px as usize];
+                    pixel.color += sample.color * (weight * included);
+                    pixel.weight += weight * included;
+                    pixel.coverage += weight * sample.color.a;
+                    pixel.total += weight;
+                    pixel.albedo += sample.albedo * weight;
+                    pixel.normal += sample.normal * weight;
+                }
+            }
+        }
+
+        for (i, row) in local.iter().enumerate() {
+            let mut shared = self.rows[first as usize + i].lock().unwrap();
+            for (pixel, accumulated) in shared.iter_mut().zip(row.iter()) {
+                pixel.merge(accumulated);
+            }
+        }
+    }
+
+    /// Width of the image in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height of the image in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Reconstructed color of a pixel, with alpha giving the fraction of it covered
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        let pixel = self.rows[y as usize].lock().unwrap()[x as usize];
+        let alpha = if !self.transparent {
+            1.0
+        } else if pixel.total.abs() > 1e-8 {
+            (pixel.coverage / pixel.total).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        if pixel.weight.abs() > 1e-8 {
+            // Negative lobes of the filter, and wavelengths outside the gamut of a spectral
+            // render, can leave the estimate slightly below zero
+            let c = pixel.color / pixel.weight;
+            Color::new(c.r.max(0.0), c.g.max(0.0), c.b.max(0.0), alpha)
+        } else {
+            Color::new(0.0, 0.0, 0.0, alpha)
+        }
+    }
+
+    /// Reconstructed albedo of the surfaces seen through a pixel
+    pub fn albedo(&self, x: u32, y: u32) -> Color {
+        let pixel = self.rows[y as usize].lock().unwrap()[x as usize];
+        if pixel.total.abs() > 1e-8 {
+            pixel.albedo / pixel.total
+        } else {
+            Color::new(0.0, 0.0, 0.0, 1.0)
+        }
+    }
+
+    /// Reconstructed normal of the surfaces seen through a pixel, or zero where there are none
+    pub fn normal(&self, x: u32, y: u32) -> Vector3 {
+        let pixel = self.rows[y as usize].lock().unwrap()[x as usize];
+        if pixel.normal.magnitude() > 1e-8 {
+            pixel.normal.normalize()
+        } else {
+            Vector3::zeros()
+        }
+    }
+}
@@ -77,7 +77,7 @@ impl<P: Pixel> Image<P> {
                 scoped.execute(move || {
                     unsafe {
                         for x in 0..width {
-                            *row.0.offset(x as isize) = f(x as u32, y as u32);
+                            *row.0.add(x) = f(x as u32, y as u32);
                         }
                     }
                 })
@@ -105,7 +105,7 @@ impl<P: Pixel<Subpixel=u8> + 'static> Image<P> {
         let img = ImageBuffer::from_fn(self.width as u32, self.height as u32, |x, y| -> P {
             let row = &self.rows[y as usize];
             unsafe {
-                let (a, b, c, d) = (*row.0.offset(x as isize)).channels4();
+                let (a, b, c, d) = (*row.0.add(x as usize)).channels4();
                 P::from_channels(a, b, c, d)
             }
         });
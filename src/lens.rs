@@ -0,0 +1,401 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use failure::{bail, Error};
+use rand::random;
+
+use crate::camera::{Camera, View};
+use crate::microfacet::refract;
+use crate::types::{Ray, Scalar, Vector3};
+
+/// Lens prescriptions are given in millimetres, while scenes are taken to be in metres
+const MILLIMETRES: Scalar = 0.001;
+
+/// Resolution of the grid of points tested on the rear element when bounding the exit pupil
+const PUPIL_GRID: usize = 64;
+
+/// Number of points along a radius of the film from which the exit pupil is bounded
+const PUPIL_FILM_STEPS: usize = 16;
+
+/// Single spherical surface of a lens, or the aperture stop where the radius is zero
+#[derive(Debug, Clone)]
+pub struct LensSurface {
+    /// Radius of curvature, positive where the center lies behind the surface
+    pub radius: Scalar,
+    /// Distance along the axis to the next surface, or to the film for the last
+    pub thickness: Scalar,
+    /// Index of refraction of the medium behind the surface
+    pub ior: Scalar,
+    /// Diameter of the clear aperture of the surface
+    pub aperture: Scalar,
+}
+
+/// Camera tracing rays from the film back through the surfaces of a real lens prescription,
+/// giving the bokeh, vignetting, and distortion of the lens. In the frame of the lens the
+/// axis runs along z from the vertex of the front surface at zero toward the film, with all
+/// lengths in millimetres.
+#[derive(Debug, Clone)]
+pub struct RealisticLens {
+    view: View,
+    surfaces: Vec<LensSurface>,
+    /// Position of the vertex of each surface along the axis
+    vertices: Vec<Scalar>,
+    film_z: Scalar,
+    film_width: Scalar,
+    film_height: Scalar,
+    /// Bounds of the exit pupil on the plane of the rear vertex as minimum and maximum x and y,
+    /// for bands of distance from the center of the film along the x axis
+    pupil: Vec<[Scalar; 4]>,
+    /// Expected weight of rays leaving the center of the film, by which all weights are divided
+    exposure: Scalar,
+}
+
+/// Load the surfaces of a lens prescription from a file of lines holding the radius,
+/// thickness, index of refraction, and aperture diameter of each, ignoring comments
+pub fn load_prescription<P: AsRef<Path>>(path: P) -> Result<Vec<LensSurface>, Error> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut surfaces = vec![];
+
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let values = line
+            .split_whitespace()
+            .map(|s| s.parse::<Scalar>())
+            .collect::<Result<Vec<_>, _>>()?;
+        if values.len() != 4 {
+            bail!("invalid lens surface: {}", line);
+        }
+
+        surfaces.push(LensSurface {
+            radius: values[0],
+            thickness: values[1],
+            ior: if values[2] == 0.0 { 1.0 } else { values[2] },
+            aperture: values[3],
+        });
+    }
+
+    if surfaces.is_empty() {
+        bail!("lens prescription has no surfaces");
+    }
+
+    Ok(surfaces)
+}
+
+impl RealisticLens {
+    /// Create a camera from the surfaces of a lens, with film of the given diagonal in
+    /// millimetres and aspect ratio, focused at a distance in front of the lens
+    pub fn new(
+        view: &View,
+        surfaces: Vec<LensSurface>,
+        film_diagonal: Scalar,
+        aspect_ratio: Scalar,
+        focus_distance: Scalar,
+    ) -> Result<Self, Error> {
+        let mut vertices = Vec::with_capacity(surfaces.len());
+        let mut z = 0.0;
+        for surface in &surfaces {
+            vertices.push(z);
+            z += surface.thickness;
+        }
+
+        let film_height = film_diagonal / (1.0 + aspect_ratio * aspect_ratio).sqrt();
+        let mut lens = Self {
+            view: view.clone(),
+            surfaces,
+            vertices,
+            film_z: z,
+            film_width: film_height * aspect_ratio,
+            film_height,
+            pupil: vec![],
+            exposure: 1.0,
+        };
+
+        lens.film_z = lens.focus(focus_distance / MILLIMETRES)?;
+        lens.pupil = lens.exit_pupil()?;
+        lens.exposure = lens.center_weight();
+        Ok(lens)
+    }
+
+    /// Follow a ray through each surface of the lens in turn, toward the film if forward or
+    /// toward the scene otherwise, returning None where the ray is blocked
+    fn trace(
+        &self,
+        origin: Vector3,
+        direction: Vector3,
+        forward: bool,
+    ) -> Option<(Vector3, Vector3)> {
+        let (mut o, mut d) = (origin, direction.normalize());
+        let count = self.surfaces.len();
+
+        for step in 0..count {
+            let i = if forward { step } else { count - 1 - step };
+            let surface = &self.surfaces[i];
+            let vertex = self.vertices[i];
+            let radius = surface.aperture / 2.0;
+
+            if surface.radius == 0.0 {
+                if d.z == 0.0 {
+                    return None;
+                }
+                let p = o + d * ((vertex - o.z) / d.z);
+                if p.x * p.x + p.y * p.y > radius * radius {
+                    return None;
+                }
+                o = p;
+                continue;
+            }
+
+            // Of the two intersections with the sphere, the surface is the one on the side of
+            // the vertex rather than the far side of the center
+            let center = Vector3::new(0.0, 0.0, vertex + surface.radius);
+            let oc = o - center;
+            let b = oc.dot(&d);
+            let c = oc.dot(&oc) - surface.radius * surface.radius;
+            let discriminant = b * b - c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let p = [-b - discriminant.sqrt(), -b + discriminant.sqrt()]
+                .iter()
+                .filter(|&&t| t > 0.0)
+                .map(|&t| o + d * t)
+                .find(|p| (p.z - center.z) * surface.radius < 0.0)?;
+            if p.x * p.x + p.y * p.y > radius * radius {
+                return None;
+            }
+
+            let before = if i == 0 {
+                1.0
+            } else {
+                self.surfaces[i - 1].ior
+            };
+            let eta = if forward {
+                surface.ior / before
+            } else {
+                before / surface.ior
+            };
+            let mut n = (p - center).normalize();
+            if n.dot(&d) > 0.0 {
+                n = -n;
+            }
+            d = refract(&-d, &n, eta)?.normalize();
+            o = p;
+        }
+
+        Some((o, d))
+    }
+
+    /// Positions along the axis of the object and image principal planes, and the focal
+    /// length, found by tracing rays parallel to the axis through the lens either way
+    fn principal_planes(&self) -> Result<(Scalar, Scalar, Scalar), Error> {
+        let length: Scalar = self.surfaces.iter().map(|s| s.thickness).sum();
+        let h = 0.05 * self.surfaces[self.surfaces.len() - 1].aperture / 2.0;
+
+        let image = self.trace(Vector3::new(h, 0.0, -1.0), Vector3::z(), true);
+        let object = self.trace(Vector3::new(h, 0.0, length + 1.0), -Vector3::z(), false);
+        let ((io, id), (oo, od)) = match (image, object) {
+            (Some(image), Some(object)) if image.1.x < 0.0 && object.1.x < 0.0 => (image, object),
+            _ => bail!("lens does not bring parallel rays to a focus"),
+        };
+
+        // Principal planes lie where the outgoing rays reach the height of the incoming ones
+        let image_focus = io.z - io.x / id.x * id.z;
+        let image_principal = io.z + (h - io.x) / id.x * id.z;
+        let object_principal = oo.z + (h - oo.x) / od.x * od.z;
+
+        Ok((
+            object_principal,
+            image_principal,
+            image_focus - image_principal,
+        ))
+    }
+
+    /// Position of the film bringing objects at a distance in front of the lens into focus
+    fn focus(&self, distance: Scalar) -> Result<Scalar, Error> {
+        let (object_principal, image_principal, focal_length) = self.principal_planes()?;
+
+        let object_distance = object_principal + distance;
+        if object_distance <= focal_length {
+            bail!("focus distance is closer than the focal length of the lens");
+        }
+        let image_distance = 1.0 / (1.0 / focal_length - 1.0 / object_distance);
+
+        Ok(image_principal + image_distance)
+    }
+
+    /// Expected weight, before normalizing, of rays leaving the center of the film toward the
+    /// bounds of the exit pupil, with those blocked by the lens carrying none
+    fn center_weight(&self) -> Scalar {
+        let [x0, x1, y0, y1] = self.pupil[0];
+        let film = Vector3::new(0.0, 0.0, self.film_z);
+        let z = self.vertices[self.vertices.len() - 1];
+        let mut sum = 0.0;
+        for i in 0..PUPIL_GRID {
+            for j in 0..PUPIL_GRID {
+                let x = x0 + (x1 - x0) * (i as Scalar + 0.5) / PUPIL_GRID as Scalar;
+                let y = y0 + (y1 - y0) * (j as Scalar + 0.5) / PUPIL_GRID as Scalar;
+                let direction = Vector3::new(x, y, z) - film;
+                if self.trace(film, direction, false).is_some() {
+                    sum += direction.normalize().z.powi(4);
+                }
+            }
+        }
+
+        (x1 - x0) * (y1 - y0) * sum / (PUPIL_GRID * PUPIL_GRID) as Scalar
+    }
+
+    /// Bound the points on the plane of the rear vertex through which light reaches the film,
+    /// for each of a number of bands of distance from the center of the film. As the lens is
+    /// symmetric about its axis, each is found for points along the x axis alone.
+    fn exit_pupil(&self) -> Result<Vec<[Scalar; 4]>, Error> {
+        let rear = self.surfaces.len() - 1;
+        let radius = self.surfaces[rear].aperture / 2.0;
+        let z = self.vertices[rear];
+        let cell = 2.0 * radius / PUPIL_GRID as Scalar;
+        let half_diagonal = 0.5 * self.film_width.hypot(self.film_height);
+
+        let mut pupil = Vec::with_capacity(PUPIL_FILM_STEPS);
+        for k in 0..PUPIL_FILM_STEPS {
+            let mut bounds = [Scalar::MAX, Scalar::MIN, Scalar::MAX, Scalar::MIN];
+            for end in k..k + 2 {
+                let r = half_diagonal * end as Scalar / PUPIL_FILM_STEPS as Scalar;
+                let film = Vector3::new(r, 0.0, self.film_z);
+                for i in 0..PUPIL_GRID {
+                    for j in 0..PUPIL_GRID {
+                        let x = -radius + (i as Scalar + 0.5) * cell;
+                        let y = -radius + (j as Scalar + 0.5) * cell;
+                        if self
+                            .trace(film, Vector3::new(x, y, z) - film, false)
+                            .is_some()
+                        {
+                            bounds = [
+                                bounds[0].min(x),
+                                bounds[1].max(x),
+                                bounds[2].min(y),
+                                bounds[3].max(y),
+                            ];
+                        }
+                    }
+                }
+            }
+
+            if bounds[0] > bounds[1] {
+                if k == 0 {
+                    bail!("no light passes through the lens to the center of the film");
+                }
+                bounds = [0.0; 4];
+            }
+
+            // Pad by a cell to cover points between those tested
+            pupil.push([
+                bounds[0] - cell,
+                bounds[1] + cell,
+                bounds[2] - cell,
+                bounds[3] + cell,
+            ]);
+        }
+
+        Ok(pupil)
+    }
+}
+
+impl Camera for RealisticLens {
+    /// Get a ray leaving the front of the lens from the point of the film at coordinates s and
+    /// t, with no weight where the lens blocks it. As in pbrt, the ray is weighted by the area of the
+    /// bounds of the exit pupil it was chosen within and the cosine to the fourth of its angle
+    /// to the axis, relative to the center of the film so that exposure there matches the
+    /// other cameras, giving the falloff of light toward the corners.
+    fn get_ray(&self, s: Scalar, t: Scalar) -> Option<(Ray, Scalar)> {
+        // The lens inverts the image, so the film is flipped to keep it upright
+        let film = Vector3::new(
+            (0.5 - s) * self.film_width,
+            (0.5 - t) * self.film_height,
+            self.film_z,
+        );
+
+        // Choose a point within the bounds of the exit pupil for the distance of the film point
+        // from the center, rotated from the x axis to the angle of the film point
+        let r = film.x.hypot(film.y);
+        let half_diagonal = 0.5 * self.film_width.hypot(self.film_height);
+        let band =
+            ((r / half_diagonal * PUPIL_FILM_STEPS as Scalar) as usize).min(PUPIL_FILM_STEPS - 1);
+        let [x0, x1, y0, y1] = self.pupil[band];
+        let x = x0 + (x1 - x0) * random::<Scalar>();
+        let y = y0 + (y1 - y0) * random::<Scalar>();
+        let (sin, cos) = if r > 0.0 {
+            (film.y / r, film.x / r)
+        } else {
+            (0.0, 1.0)
+        };
+        let target = Vector3::new(
+            x * cos - y * sin,
+            x * sin + y * cos,
+            self.vertices[self.vertices.len() - 1],
+        );
+
+        let cos = (target - film).normalize().z.abs();
+        let weight = (x1 - x0) * (y1 - y0) * cos.powi(4) / self.exposure;
+
+        // Light blocked by the lens still covers the film, so is given as a ray carrying none
+        match self.trace(film, target - film, false) {
+            Some((o, d)) => Some((
+                Ray::new(
+                    self.view.origin + self.view.to_world(&(o * MILLIMETRES)),
+                    self.view.to_world(&d),
+                ),
+                weight,
+            )),
+            None => Some((
+                Ray::new(self.view.origin, self.view.to_world(&-Vector3::z())),
+                0.0,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Point3;
+
+    fn dgauss() -> RealisticLens {
+        let view = View::new(Point3::origin(), Point3::new(0.0, 0.0, -1.0), Vector3::y());
+        let surfaces = load_prescription("lenses/dgauss.50mm.dat").unwrap();
+        RealisticLens::new(&view, surfaces, 43.27, 1.5, 10.0).unwrap()
+    }
+
+    #[test]
+    fn dgauss_has_50mm_focal_length() {
+        let (_, _, focal_length) = dgauss().principal_planes().unwrap();
+        assert!((focal_length - 50.0).abs() < 1.0, "{}", focal_length);
+    }
+
+    #[test]
+    fn focus_at_infinity_is_one_focal_length_behind_the_principal_plane() {
+        let lens = dgauss();
+        let (_, image_principal, focal_length) = lens.principal_planes().unwrap();
+        let film = lens.focus(1e9).unwrap();
+        assert!((film - image_principal - focal_length).abs() < 1e-3);
+    }
+
+    #[test]
+    fn focus_moves_the_film_back_for_closer_objects() {
+        let lens = dgauss();
+        assert!(lens.focus(1000.0).unwrap() > lens.focus(10000.0).unwrap());
+    }
+
+    #[test]
+    fn center_of_the_film_is_exposed_as_by_the_other_cameras() {
+        let lens = dgauss();
+        let count = 100_000;
+        let total: Scalar = (0..count).map(|_| lens.get_ray(0.5, 0.5).unwrap().1).sum();
+        let mean = total / count as Scalar;
+        assert!((mean - 1.0).abs() < 0.02, "{}", mean);
+    }
+}
@@ -2,131 +2,380 @@ use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 use clap::{value_t_or_exit, App, Arg};
-use failure::Error;
+use failure::{bail, Error};
 use log::info;
 use pbr::ProgressBar;
 use rand::random;
 
+mod animation;
+mod blend;
 mod camera;
+mod coatings;
+mod denoise;
+mod environment;
+mod filters;
+mod framebuffer;
 mod image;
+mod lens;
 mod materials;
+mod media;
+mod microfacet;
+mod noise;
+mod normals;
+mod principled;
+mod scenes;
 mod shapes;
+mod spectrum;
+mod subsurface;
+mod textures;
 mod types;
+mod voxels;
+mod world;
 
-use crate::camera::Camera;
-use crate::materials::{Dialectric, Lambertian, Metal};
-use crate::shapes::{Scene, Shape, Sphere};
+use crate::animation::{frame_path, parse_frames, CameraPath, Keyframe};
+use crate::camera::{
+    Aperture, ApertureShape, Camera, CubeMap, Equirectangular, Fisheye, FisheyeMapping,
+    Orthographic, Stereo, StereoLayout, ThinLens, View,
+};
+use crate::denoise::Denoiser;
+use crate::environment::{Environment, EnvironmentMap, Gradient, PhysicalSky};
+use crate::filters::{
+    BoxFilter, Filter, GaussianFilter, LanczosFilter, MitchellFilter, TentFilter,
+};
+use crate::framebuffer::{Framebuffer, Sample};
+use crate::lens::{load_prescription, RealisticLens};
+use crate::materials::ScatterKind;
+use crate::media::{Fog, HenyeyGreenstein, Isotropic, PhaseFunction, Volumetric};
+use crate::shapes::{HitResult, Shape};
+use crate::spectrum::SpectralFilm;
+use crate::textures::ImageTexture;
 use crate::types::{Color, Point3, Ray, Scalar, Vector3};
+use crate::voxels::VoxelGrid;
+use crate::world::World;
 
-/// Determine the color contribution from a given camera ray
-fn color(ray: &Ray, scene: &Scene, maxdepth: u32, depth: u32) -> Color {
-    if let Some(hit) = scene.hit(&ray, 0.001, std::f32::MAX) {
-        if depth >= maxdepth {
-            return Color::new(0.0, 0.0, 0.0, 1.0);
+/// Weight a sample taken with one strategy against another using the power heuristic
+fn power_heuristic(pdf: Scalar, other_pdf: Scalar) -> Scalar {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+/// Settings of the path tracing integrator
+#[derive(Debug, Clone)]
+struct Integrator {
+    /// Maximum number of bounces along a path
+    maxdepth: u32,
+    /// Maximum number of diffuse bounces along a path
+    max_diffuse: u32,
+    /// Maximum number of specular bounces along a path
+    max_specular: u32,
+    /// Maximum number of transmission bounces along a path
+    max_transmission: u32,
+    /// Number of bounces after which paths are terminated at random by Russian roulette
+    roulette_depth: u32,
+    /// Largest component of light reaching the camera from the environment or an emitter after
+    /// a single bounce, beyond which it is scaled back
+    clamp_direct: Option<Scalar>,
+    /// Largest component of light reaching the camera after further bounces, beyond which it is
+    /// scaled back
+    clamp_indirect: Option<Scalar>,
+}
+
+impl Integrator {
+    /// Whether a path has taken no more bounces of each kind than allowed
+    fn within_limits(&self, path: &PathState) -> bool {
+        path.diffuse <= self.max_diffuse
+            && path.specular <= self.max_specular
+            && path.transmission <= self.max_transmission
+    }
+
+    /// Probability with which a path continues past its next bounce. Beyond the roulette depth
+    /// paths carrying little light are likely to end, with those surviving weighted up to
+    /// make up for the others.
+    fn survival(&self, path: &PathState) -> Scalar {
+        if path.depth < self.roulette_depth {
+            return 1.0;
         }
+        let t = &path.throughput;
+        t.r.max(t.g).max(t.b).min(1.0)
+    }
+}
 
-        if let Some(scattered) = hit.material.scatter(&ray, &hit) {
-            return scattered.attenuation * color(&scattered.ray, scene, maxdepth, depth + 1);
+/// Bounces taken along a path from the camera so far
+#[derive(Debug, Clone, Copy)]
+struct PathState {
+    depth: u32,
+    diffuse: u32,
+    specular: u32,
+    transmission: u32,
+    /// Product of the attenuation of each bounce, weighted up for surviving Russian roulette,
+    /// being the fraction of light found at the end of the path which reaches the camera
+    throughput: Color,
+}
+
+impl PathState {
+    /// Path leaving the camera
+    fn camera() -> Self {
+        Self {
+            depth: 0,
+            diffuse: 0,
+            specular: 0,
+            transmission: 0,
+            throughput: Color::new(1.0, 1.0, 1.0, 1.0),
         }
     }
 
-    let unit_direction = ray.direction.normalize();
-    let t = 0.5 * (unit_direction.y + 1.0);
-    let c = (1.0 - t) * Vector3::new(1.0, 1.0, 1.0) + t * Vector3::new(0.5, 0.7, 1.0);
+    /// Path continuing after a further bounce
+    fn bounce(&self, kind: ScatterKind, attenuation: Color) -> Self {
+        let mut next = Self {
+            depth: self.depth + 1,
+            throughput: self.throughput * attenuation,
+            ..*self
+        };
+        match kind {
+            ScatterKind::Diffuse => next.diffuse += 1,
+            ScatterKind::Specular => next.specular += 1,
+            ScatterKind::Transmission => next.transmission += 1,
+        }
+        next
+    }
+}
 
-    c.into()
+/// Counts of the bounces taken by paths and the reasons they ended
+#[derive(Debug, Clone)]
+struct PathStatistics {
+    /// Number of paths reaching each bounce, starting from the camera
+    bounces: Vec<u64>,
+    /// Paths escaping to the background
+    escaped: u64,
+    /// Paths absorbed by a surface or volume
+    absorbed: u64,
+    /// Paths reaching the maximum depth or the limit for a kind of bounce
+    limited: u64,
+    /// Paths ended by Russian roulette
+    roulette: u64,
 }
 
-/// Generate a random scene containing spheres of various sizes and materials
-fn generate_scene() -> Scene {
-    let mut scene: Scene = vec![];
-    scene.push(Arc::new(Sphere {
-        center: Point3::new(0.0, -1000.0, 0.0),
-        radius: 1000.0,
-        material: Arc::new(Lambertian {
-            albedo: Color::new(0.5, 0.5, 0.5, 1.0),
-        }),
-    }));
-
-    let avoid = Vector3::new(4.0, 0.2, 0.0);
-    for a in -11..11 {
-        for b in -11..11 {
-            let center = Point3::new(
-                (a as Scalar) + 0.9 * random::<Scalar>(),
-                0.2,
-                (b as Scalar) + 0.9 * random::<Scalar>(),
-            );
-            let choose_mat = random::<Scalar>();
-
-            if (center - avoid).coords.magnitude() > 0.9 {
-                if choose_mat < 0.8 {
-                    let albedo = Color::new(
-                        random::<Scalar>() * random::<Scalar>(),
-                        random::<Scalar>() * random::<Scalar>(),
-                        random::<Scalar>() * random::<Scalar>(),
-                        1.0,
-                    );
-
-                    scene.push(Arc::new(Sphere {
-                        center,
-                        radius: 0.2,
-                        material: Arc::new(Lambertian { albedo }),
-                    }));
-                } else if choose_mat < 0.95 {
-                    let albedo = Color::new(
-                        0.5 * (1.0 + random::<Scalar>()),
-                        0.5 * (1.0 + random::<Scalar>()),
-                        0.5 * (1.0 + random::<Scalar>()),
-                        1.0,
-                    );
-                    let roughness = 0.5 * random::<Scalar>();
-
-                    scene.push(Arc::new(Sphere {
-                        center,
-                        radius: 0.2,
-                        material: Arc::new(Metal { albedo, roughness }),
-                    }));
-                } else {
-                    let albedo = Color::new(1.0, 1.0, 1.0, 1.0);
-                    let ior = 1.5;
-
-                    scene.push(Arc::new(Sphere {
-                        center,
-                        radius: 0.2,
-                        material: Arc::new(Dialectric { albedo, ior }),
-                    }));
-                }
+impl PathStatistics {
+    /// Empty statistics for paths of up to the given depth
+    fn new(maxdepth: u32) -> Self {
+        Self {
+            bounces: vec![0; maxdepth as usize + 1],
+            escaped: 0,
+            absorbed: 0,
+            limited: 0,
+            roulette: 0,
+        }
+    }
+
+    /// Add the counts of other statistics to these
+    fn merge(&mut self, other: &PathStatistics) {
+        for (count, other) in self.bounces.iter_mut().zip(other.bounces.iter()) {
+            *count += other;
+        }
+        self.escaped += other.escaped;
+        self.absorbed += other.absorbed;
+        self.limited += other.limited;
+        self.roulette += other.roulette;
+    }
+
+    /// Log the counts, with the share of paths reaching each bounce
+    fn log(&self) {
+        let paths = self.bounces[0].max(1) as f64;
+        for (depth, &count) in self.bounces.iter().enumerate() {
+            if count > 0 {
+                info!(
+                    "Bounce {}: {} paths ({:.1}%)",
+                    depth,
+                    count,
+                    100.0 * count as f64 / paths
+                );
             }
         }
+        info!(
+            "Paths escaped: {}, absorbed: {}, limited: {}, ended by roulette: {}",
+            self.escaped, self.absorbed, self.limited, self.roulette
+        );
     }
+}
 
-    scene.push(Arc::new(Sphere {
-        center: Point3::new(0.0, 1.0, 0.0),
-        radius: 1.0,
-        material: Arc::new(Dialectric {
-            albedo: Color::new(1.0, 1.0, 1.0, 1.0),
-            ior: 1.5,
-        }),
-    }));
+/// Scale back a color so that none of its components exceed a limit, keeping its hue. This
+/// trades a little energy for the removal of fireflies from rarely sampled bright paths.
+fn clamp_radiance(c: Color, limit: Option<Scalar>) -> Color {
+    let max = c.r.max(c.g).max(c.b);
+    match limit {
+        Some(limit) if max > limit => c * (limit / max),
+        _ => c,
+    }
+}
 
-    scene.push(Arc::new(Sphere {
-        center: Point3::new(-4.0, 1.0, 0.0),
-        radius: 1.0,
-        material: Arc::new(Lambertian {
-            albedo: Color::new(0.4, 0.2, 0.1, 1.0),
-        }),
-    }));
-
-    scene.push(Arc::new(Sphere {
-        center: Point3::new(4.0, 1.0, 0.0),
-        radius: 1.0,
-        material: Arc::new(Metal {
-            albedo: Color::new(0.7, 0.6, 0.5, 1.0),
-            roughness: 0.0,
-        }),
-    }));
+/// Estimate light arriving at a hit directly from the environment by sampling it
+fn direct_lighting(ray: &Ray, hit: &HitResult, world: &World) -> Color {
+    let black = Color::new(0.0, 0.0, 0.0, 1.0);
+    let (direction, radiance, light_pdf) = match world.environment.sample() {
+        Some(sample) => sample,
+        None => return black,
+    };
+
+    let f = match hit.material.eval(ray, hit, &direction) {
+        Some(f) => f,
+        None => return black,
+    };
+    let bsdf_pdf = hit.material.pdf(ray, hit, &direction).unwrap_or(0.0);
+
+    let transmittance = world.transmittance(&Ray::new(hit.p, direction), Scalar::MAX);
+    if transmittance <= 0.0 {
+        return black;
+    }
 
-    scene
+    f * radiance * (transmittance * power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
+}
+
+/// Determine the color contribution from a given camera ray, following the path it takes
+/// through the scene one bounce at a time and counting the bounces in the given statistics. The
+/// alpha of the result is 1.0 where the ray hits something and 0.0 where it escapes to the
+/// background. Also gives the albedo and normal of the first surface hit for the denoiser, with
+/// rays escaping to the background seeing a white albedo and no normal.
+fn color(
+    ray: &Ray,
+    world: &World,
+    integrator: &Integrator,
+    stats: &mut PathStatistics,
+) -> (Color, Color, Vector3) {
+    let mut ray = ray.clone();
+    let mut path = PathState::camera();
+    let mut covered = false;
+    let mut albedo = Color::new(1.0, 1.0, 1.0, 1.0);
+    let mut normal = Vector3::zeros();
+
+    // When light was sampled directly at the previous bounce, the density with which the ray
+    // was chosen there, so that the two estimates may be combined
+    let mut bsdf_pdf = None;
+
+    // Light reaching the camera after bounces is clamped as a whole, with both halves of the
+    // estimate of light arriving at the first bounce, from sampling the environment and from
+    // following the scattered ray, counted as direct and that from further bounces as indirect
+    let mut radiance = Color::new(0.0, 0.0, 0.0, 1.0);
+    let mut direct = Color::new(0.0, 0.0, 0.0, 1.0);
+    let mut indirect = Color::new(0.0, 0.0, 0.0, 1.0);
+
+    loop {
+        stats.bounces[path.depth as usize] += 1;
+        let surface = world.scene.hit(&ray, 0.001, Scalar::MAX);
+
+        // Rays may scatter off of particles in the fog before reaching the nearest surface
+        let t_max = surface.as_ref().map_or(Scalar::MAX, |hit| hit.t);
+        let hit = world
+            .fog
+            .as_ref()
+            .and_then(|fog| fog.hit(&ray, t_max))
+            .or(surface);
+
+        let hit = match hit {
+            Some(hit) => hit,
+            None => {
+                let background = world.environment.color(&ray.direction);
+                let weight = match bsdf_pdf {
+                    Some(pdf) => power_heuristic(pdf, world.environment.pdf(&ray.direction)),
+                    None => 1.0,
+                };
+                let light = path.throughput * background * weight;
+                match path.depth {
+                    0 => radiance += light,
+                    1 => direct += light,
+                    _ => indirect += light,
+                }
+                stats.escaped += 1;
+                break;
+            }
+        };
+        if path.depth == 0 {
+            covered = true;
+            albedo = hit.material.albedo(&hit);
+            normal = hit.normal;
+        }
+
+        // Light entering a translucent shape wanders through its interior before reaching the
+        // surface again from inside
+        let material = hit.material.clone();
+        let hit = match material.interior() {
+            Some(interior) if ray.direction.dot(&hit.normal) > 0.0 => {
+                match interior.walk(&world.scene, &ray, hit) {
+                    Some((exit, hit, weight)) => {
+                        path.throughput *= weight;
+                        ray = exit;
+                        hit
+                    }
+                    None => {
+                        stats.absorbed += 1;
+                        break;
+                    }
+                }
+            }
+            _ => hit,
+        };
+
+        let emitted = path.throughput * hit.material.emitted(&hit);
+        match path.depth {
+            0 => {
+                // Light seen directly is left as it is by the denoiser
+                if emitted.luminance() > 0.0 {
+                    albedo = Color::new(1.0, 1.0, 1.0, 1.0);
+                }
+                radiance += emitted;
+            }
+            1 => direct += emitted,
+            _ => indirect += emitted,
+        }
+
+        // Paths out of bounces still follow their last scattered ray this far, picking up the
+        // share of light which sampling the environment at the bounce before left to it
+        if path.depth >= integrator.maxdepth || !integrator.within_limits(&path) {
+            stats.limited += 1;
+            break;
+        }
+
+        let mut scattered = match hit.material.scatter(&ray, &hit) {
+            Some(scattered) => scattered,
+            None => {
+                stats.absorbed += 1;
+                break;
+            }
+        };
+        scattered.ray.wavelength = ray.wavelength;
+
+        let light = path.throughput * direct_lighting(&ray, &hit, world);
+        if path.depth == 0 {
+            direct += light;
+        } else {
+            indirect += light;
+        }
+
+        bsdf_pdf = hit.material.pdf(&ray, &hit, &scattered.ray.direction);
+        let next = path.bounce(scattered.kind, scattered.attenuation);
+        let survival = integrator.survival(&next);
+        if random::<Scalar>() >= survival {
+            stats.roulette += 1;
+            break;
+        }
+
+        path = PathState {
+            throughput: next.throughput / survival,
+            ..next
+        };
+        ray = scattered.ray;
+    }
+
+    let color = Color {
+        a: if covered { 1.0 } else { 0.0 },
+        ..radiance
+            + clamp_radiance(direct, integrator.clamp_direct)
+            + clamp_radiance(indirect, integrator.clamp_indirect)
+    };
+    (color, albedo, normal)
 }
 
 fn main() -> Result<(), Error> {
@@ -183,6 +432,344 @@ fn main() -> Result<(), Error> {
                 .takes_value(true)
                 .default_value("50"),
         )
+        .arg(
+            Arg::with_name("max-diffuse")
+                .long("max-diffuse")
+                .value_name("DEPTH")
+                .help("Maximum number of diffuse bounces, defaulting to the maximum depth")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-specular")
+                .long("max-specular")
+                .value_name("DEPTH")
+                .help("Maximum number of specular bounces, defaulting to the maximum depth")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-transmission")
+                .long("max-transmission")
+                .value_name("DEPTH")
+                .help("Maximum number of transmission bounces, defaulting to the maximum depth")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("roulette-depth")
+                .long("roulette-depth")
+                .value_name("DEPTH")
+                .help("Number of bounces after which paths carrying little light end at random")
+                .takes_value(true)
+                .default_value("5"),
+        )
+        .arg(
+            Arg::with_name("statistics")
+                .long("statistics")
+                .help("Log how many paths reach each bounce and how they end"),
+        )
+        .arg(
+            Arg::with_name("clamp-direct")
+                .long("clamp-direct")
+                .value_name("LIMIT")
+                .help("Clamp light reaching the first bounce directly from lights, to reduce fireflies")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("clamp-indirect")
+                .long("clamp-indirect")
+                .value_name("LIMIT")
+                .help("Clamp light reaching the first bounce from further bounces")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("reject-outliers")
+                .long("reject-outliers")
+                .value_name("DEVIATIONS")
+                .help("Pull back samples brighter than the rest of their pixel, e.g. 3.0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("scene")
+                .long("scene")
+                .value_name("SCENE")
+                .help("Built-in scene to render")
+                .takes_value(true)
+                .possible_values(&[
+                    "random",
+                    "textures",
+                    "noise",
+                    "volumes",
+                    "bumps",
+                    "metals",
+                    "glass",
+                    "principled",
+                    "dispersion",
+                    "coatings",
+                    "subsurface",
+                    "blends",
+                ])
+                .default_value("random"),
+        )
+        .arg(
+            Arg::with_name("volume")
+                .long("volume")
+                .value_name("FILE")
+                .help("Voxel grid file to render in place of the fire in the volumes scene")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("texture")
+                .long("texture")
+                .value_name("FILE")
+                .help("Image file to wrap around a sphere in the textures scene")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("normal-map")
+                .long("normal-map")
+                .value_name("FILE")
+                .help("Tangent space normal map to apply to a sphere in the bumps scene")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("SEED")
+                .help("Seed for procedural noise textures")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("environment")
+                .long("environment")
+                .value_name("FILE")
+                .help("Equirectangular environment map to light the scene with, e.g. a .hdr file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("environment-rotation")
+                .long("environment-rotation")
+                .value_name("DEGREES")
+                .help("Rotation of the environment map about the vertical axis")
+                .takes_value(true)
+                .default_value("0.0"),
+        )
+        .arg(
+            Arg::with_name("environment-intensity")
+                .long("environment-intensity")
+                .value_name("INTENSITY")
+                .help("Brightness multiplier for the environment map or physical sky")
+                .takes_value(true)
+                .default_value("1.0"),
+        )
+        .arg(
+            Arg::with_name("sky")
+                .long("sky")
+                .value_name("SKY")
+                .help("Model of the sky used when no environment map is given")
+                .takes_value(true)
+                .possible_values(&["gradient", "physical"])
+                .default_value("gradient"),
+        )
+        .arg(
+            Arg::with_name("sun-elevation")
+                .long("sun-elevation")
+                .value_name("DEGREES")
+                .help("Angle of the sun above the horizon for the physical sky")
+                .takes_value(true)
+                .default_value("45.0"),
+        )
+        .arg(
+            Arg::with_name("sun-azimuth")
+                .long("sun-azimuth")
+                .value_name("DEGREES")
+                .help("Direction of the sun about the vertical axis for the physical sky")
+                .takes_value(true)
+                .default_value("0.0"),
+        )
+        .arg(
+            Arg::with_name("turbidity")
+                .long("turbidity")
+                .value_name("TURBIDITY")
+                .help("Haziness of the physical sky, from 2.0 (clear) to 10.0 (hazy)")
+                .takes_value(true)
+                .default_value("3.0"),
+        )
+        .arg(
+            Arg::with_name("fog")
+                .long("fog")
+                .value_name("DENSITY")
+                .help("Fill the scene with atmospheric fog of the given density")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("fog-anisotropy")
+                .long("fog-anisotropy")
+                .value_name("G")
+                .help("Henyey-Greenstein asymmetry of light scattered by fog, between -1.0 and 1.0")
+                .takes_value(true)
+                .default_value("0.0"),
+        )
+        .arg(
+            Arg::with_name("fog-height")
+                .long("fog-height")
+                .value_name("HEIGHT")
+                .help("Height of the top of the fog layer")
+                .takes_value(true)
+                .default_value("3.0"),
+        )
+        .arg(
+            Arg::with_name("spectral")
+                .long("spectral")
+                .help("Trace a single sampled wavelength per path, for dispersion through glass"),
+        )
+        .arg(
+            Arg::with_name("projection")
+                .long("projection")
+                .value_name("PROJECTION")
+                .help("Projection used by the camera")
+                .takes_value(true)
+                .possible_values(&[
+                    "perspective",
+                    "orthographic",
+                    "equirectangular",
+                    "cubemap",
+                    "fisheye-equidistant",
+                    "fisheye-equisolid",
+                ])
+                .default_value("perspective"),
+        )
+        .arg(
+            Arg::with_name("fov")
+                .long("fov")
+                .value_name("DEGREES")
+                .help("Field of view, vertical for perspective and across the circle for fisheye")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ortho-height")
+                .long("ortho-height")
+                .value_name("HEIGHT")
+                .help("Height of the area seen by the orthographic projection, in world units")
+                .takes_value(true)
+                .default_value("5.0"),
+        )
+        .arg(
+            Arg::with_name("aperture")
+                .long("aperture")
+                .value_name("DIAMETER")
+                .help("Diameter of the aperture of the thin lens, in world units")
+                .takes_value(true)
+                .default_value("0.1"),
+        )
+        .arg(
+            Arg::with_name("blades")
+                .long("blades")
+                .value_name("COUNT")
+                .help("Form the aperture from a number of straight blades rather than a circle")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("blade-rotation")
+                .long("blade-rotation")
+                .value_name("DEGREES")
+                .help("Rotation of the polygonal aperture formed by --blades")
+                .takes_value(true)
+                .default_value("0.0"),
+        )
+        .arg(
+            Arg::with_name("aperture-mask")
+                .long("aperture-mask")
+                .value_name("FILE")
+                .help("Image giving the shape of the aperture, open where it is white")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("anamorphic-squeeze")
+                .long("anamorphic-squeeze")
+                .value_name("SQUEEZE")
+                .help("Horizontal squeeze of an anamorphic lens, stretching bokeh vertically")
+                .takes_value(true)
+                .default_value("1.0"),
+        )
+        .arg(
+            Arg::with_name("lens")
+                .long("lens")
+                .value_name("FILE")
+                .help("Lens prescription to trace perspective camera rays through, as in lenses/")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("film-diagonal")
+                .long("film-diagonal")
+                .value_name("MM")
+                .help("Diagonal of the film behind the lens given by --lens, in millimetres")
+                .takes_value(true)
+                .default_value("43.27"),
+        )
+        .arg(
+            Arg::with_name("stereo")
+                .long("stereo")
+                .value_name("LAYOUT")
+                .help("Render a stereo pair, omni-directional with the equirectangular projection")
+                .takes_value(true)
+                .possible_values(&["side-by-side", "top-bottom"]),
+        )
+        .arg(
+            Arg::with_name("interocular")
+                .long("interocular")
+                .value_name("DISTANCE")
+                .help("Distance between the eyes of a stereo pair, in world units")
+                .takes_value(true)
+                .default_value("0.065"),
+        )
+        .arg(
+            Arg::with_name("frames")
+                .long("frames")
+                .value_name("FIRST-LAST")
+                .help("Render a range of frames numbered in place of #s in the output, by default all")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("camera-path")
+                .long("camera-path")
+                .value_name("FILE")
+                .help("Keyframes of the camera: frame, position, look at, field of view, and focus")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("turntable")
+                .long("turntable")
+                .value_name("FRAMES")
+                .help("Circle the camera around the scene once over a number of frames")
+                .takes_value(true)
+                .conflicts_with("camera-path"),
+        )
+        .arg(
+            Arg::with_name("transparent-background")
+                .long("transparent-background")
+                .help("Leave the background transparent where camera rays miss, for compositing"),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .value_name("FILTER")
+                .help("Filter reconstructing pixels from the samples splatted around them")
+                .takes_value(true)
+                .possible_values(&["box", "tent", "gaussian", "mitchell", "lanczos"])
+                .default_value("box"),
+        )
+        .arg(
+            Arg::with_name("denoise")
+                .long("denoise")
+                .help("Denoise the image after rendering, guided by the albedo and normals seen"),
+        )
+        .arg(
+            Arg::with_name("filter-radius")
+                .long("filter-radius")
+                .value_name("PIXELS")
+                .help("Radius of the reconstruction filter, defaulting to suit the filter")
+                .takes_value(true),
+        )
         .get_matches();
 
     let output = matches
@@ -192,63 +779,586 @@ fn main() -> Result<(), Error> {
     let height = value_t_or_exit!(matches.value_of("height"), u32);
     let samples = value_t_or_exit!(matches.value_of("samples"), u32);
     let maxdepth = value_t_or_exit!(matches.value_of("maxdepth"), u32);
+    let integrator = Integrator {
+        maxdepth,
+        max_diffuse: if matches.is_present("max-diffuse") {
+            value_t_or_exit!(matches.value_of("max-diffuse"), u32)
+        } else {
+            maxdepth
+        },
+        max_specular: if matches.is_present("max-specular") {
+            value_t_or_exit!(matches.value_of("max-specular"), u32)
+        } else {
+            maxdepth
+        },
+        max_transmission: if matches.is_present("max-transmission") {
+            value_t_or_exit!(matches.value_of("max-transmission"), u32)
+        } else {
+            maxdepth
+        },
+        roulette_depth: value_t_or_exit!(matches.value_of("roulette-depth"), u32),
+        clamp_direct: if matches.is_present("clamp-direct") {
+            Some(value_t_or_exit!(matches.value_of("clamp-direct"), Scalar))
+        } else {
+            None
+        },
+        clamp_indirect: if matches.is_present("clamp-indirect") {
+            Some(value_t_or_exit!(matches.value_of("clamp-indirect"), Scalar))
+        } else {
+            None
+        },
+    };
+    let outlier_threshold = if matches.is_present("reject-outliers") {
+        Some(value_t_or_exit!(
+            matches.value_of("reject-outliers"),
+            Scalar
+        ))
+    } else {
+        None
+    };
+
+    // Limits of zero or less would black out or invert the light they apply to
+    let limits = [
+        ("clamp-direct", integrator.clamp_direct),
+        ("clamp-indirect", integrator.clamp_indirect),
+        ("reject-outliers", outlier_threshold),
+    ];
+    for (name, limit) in &limits {
+        if let Some(limit) = limit {
+            if limit.is_nan() || *limit <= 0.0 {
+                bail!("--{} must be positive", name);
+            }
+        }
+    }
+    let fog_anisotropy = value_t_or_exit!(matches.value_of("fog-anisotropy"), Scalar);
+    let fog_height = value_t_or_exit!(matches.value_of("fog-height"), Scalar);
+    let transparent_background = matches.is_present("transparent-background");
+    let denoiser = if matches.is_present("denoise") {
+        Some(Denoiser {
+            iterations: 5,
+            color_sigma: 0.5,
+            normal_sigma: 0.3,
+            albedo_sigma: 0.1,
+        })
+    } else {
+        None
+    };
+    let filter_radius = if matches.is_present("filter-radius") {
+        let radius = value_t_or_exit!(matches.value_of("filter-radius"), Scalar);
+        // Narrower filters leave pixels which no sample reaches
+        if !radius.is_finite() || radius < 0.5 {
+            bail!("filter radius must be at least half a pixel");
+        }
+        Some(radius)
+    } else {
+        None
+    };
+    let filter: Arc<dyn Filter> = match matches.value_of("filter") {
+        Some("tent") => Arc::new(TentFilter {
+            radius: filter_radius.unwrap_or(1.0),
+        }),
+        Some("gaussian") => Arc::new(GaussianFilter {
+            radius: filter_radius.unwrap_or(1.5),
+            alpha: 2.0,
+        }),
+        Some("mitchell") => Arc::new(MitchellFilter {
+            radius: filter_radius.unwrap_or(2.0),
+            b: 1.0 / 3.0,
+            c: 1.0 / 3.0,
+        }),
+        Some("lanczos") => Arc::new(LanczosFilter {
+            radius: filter_radius.unwrap_or(2.0),
+        }),
+        _ => Arc::new(BoxFilter {
+            radius: filter_radius.unwrap_or(0.5),
+        }),
+    };
+    let film = if matches.is_present("spectral") {
+        Some(SpectralFilm::default())
+    } else {
+        None
+    };
 
     info!(
         "Rendering to {} ({}x{}), {} samples, {} depth",
         &output, width, height, samples, maxdepth
     );
 
-    let scene = generate_scene();
+    let scene = match matches.value_of("scene") {
+        Some("volumes") => {
+            let grid = match matches.value_of("volume") {
+                Some(path) => Some(VoxelGrid::load(path)?),
+                None => None,
+            };
+            scenes::volumes(grid)
+        }
+        Some("textures") => {
+            let image = match matches.value_of("texture") {
+                Some(path) => Some(ImageTexture::load(path)?),
+                None => None,
+            };
+            scenes::textures(image)
+        }
+        Some("bumps") => {
+            let normal_map = match matches.value_of("normal-map") {
+                Some(path) => Some(ImageTexture::load(path)?),
+                None => None,
+            };
+            scenes::bumps(normal_map)
+        }
+        Some("metals") => scenes::metals(),
+        Some("glass") => scenes::glass(),
+        Some("principled") => scenes::principled(),
+        Some("dispersion") => scenes::dispersion(),
+        Some("coatings") => scenes::coatings(),
+        Some("subsurface") => scenes::subsurface(),
+        Some("blends") => scenes::blends(),
+        Some("noise") => scenes::noise(value_t_or_exit!(matches.value_of("seed"), u64)),
+        _ => scenes::random_spheres(),
+    };
+
+    let fog = if matches.is_present("fog") {
+        let phase: Arc<dyn PhaseFunction> = if fog_anisotropy == 0.0 {
+            Arc::new(Isotropic)
+        } else {
+            Arc::new(HenyeyGreenstein { g: fog_anisotropy })
+        };
+
+        let density = value_t_or_exit!(matches.value_of("fog"), Scalar);
+        if density < 0.0 {
+            bail!("fog density must not be negative");
+        }
+
+        Some(Fog {
+            density,
+            height: fog_height,
+            material: Arc::new(Volumetric {
+                albedo: Color::new(1.0, 1.0, 1.0, 1.0),
+                phase,
+            }),
+        })
+    } else {
+        None
+    };
+
+    let intensity = value_t_or_exit!(matches.value_of("environment-intensity"), Scalar);
+    let environment: Arc<dyn Environment> = match matches.value_of("environment") {
+        Some(path) => Arc::new(EnvironmentMap::load(
+            path,
+            value_t_or_exit!(matches.value_of("environment-rotation"), Scalar),
+            intensity,
+        )?),
+        None if matches.value_of("sky") == Some("physical") => Arc::new(PhysicalSky::new(
+            value_t_or_exit!(matches.value_of("sun-elevation"), Scalar),
+            value_t_or_exit!(matches.value_of("sun-azimuth"), Scalar),
+            value_t_or_exit!(matches.value_of("turbidity"), Scalar),
+            intensity,
+        )),
+        None => Arc::new(Gradient {
+            horizon: Color::new(1.0, 1.0, 1.0, 1.0),
+            zenith: Color::new(0.5, 0.7, 1.0, 1.0),
+        }),
+    };
+
+    let world = World {
+        scene,
+        fog,
+        environment,
+    };
 
     let lookfrom = Point3::new(13.0, 2.0, 3.0);
     let lookat = Point3::new(0.0, 0.0, 0.0);
     let aspect_ratio = (width as Scalar) / (height as Scalar);
-    let focal_length = (lookfrom - lookat).magnitude();
-
-    let camera = Camera::new(
+    let fisheye = matches
+        .value_of("projection")
+        .unwrap_or("")
+        .starts_with("fisheye");
+    let keyframe = Keyframe {
+        frame: 0.0,
         lookfrom,
         lookat,
-        Vector3::y(),
-        20.0,
-        aspect_ratio,
-        0.1,
-        focal_length,
-    );
+        fov: if matches.is_present("fov") {
+            value_t_or_exit!(matches.value_of("fov"), Scalar)
+        } else if fisheye {
+            180.0
+        } else {
+            20.0
+        },
+        focus_distance: (lookfrom - lookat).magnitude(),
+    };
+    let turntable = if matches.is_present("turntable") {
+        Some(value_t_or_exit!(matches.value_of("turntable"), u32))
+    } else {
+        None
+    };
+    let path = match (matches.value_of("camera-path"), turntable) {
+        (Some(file), _) => CameraPath::load(file)?,
+        (None, Some(0)) => bail!("turntable needs at least one frame"),
+        (None, Some(count)) => CameraPath::turntable(keyframe, count),
+        (None, None) => CameraPath::still(keyframe),
+    };
+
+    // A camera path or turntable renders each of its frames unless given a range
+    let frames = match (matches.value_of("frames"), turntable) {
+        (Some(range), _) => Some(parse_frames(range)?),
+        (None, _) if matches.is_present("camera-path") => Some(path.frames()),
+        (None, Some(count)) => Some((0, count - 1)),
+        (None, None) => None,
+    };
+
+    let prescription = match matches.value_of("lens") {
+        Some(path) => Some(load_prescription(path)?),
+        None => None,
+    };
+    let film_diagonal = value_t_or_exit!(matches.value_of("film-diagonal"), Scalar);
+    let aperture = Aperture {
+        diameter: value_t_or_exit!(matches.value_of("aperture"), Scalar),
+        shape: match (
+            matches.value_of("aperture-mask"),
+            matches.value_of("blades"),
+        ) {
+            (Some(path), _) => ApertureShape::Mask(Arc::new(ImageTexture::load(path)?)),
+            (None, Some(_)) => ApertureShape::Polygon {
+                blades: value_t_or_exit!(matches.value_of("blades"), u32),
+                rotation: value_t_or_exit!(matches.value_of("blade-rotation"), Scalar),
+            },
+            (None, None) => ApertureShape::Circular,
+        },
+        squeeze: value_t_or_exit!(matches.value_of("anamorphic-squeeze"), Scalar),
+    };
+    if aperture.squeeze <= 0.0 {
+        bail!("anamorphic squeeze must be positive");
+    }
+    if let ApertureShape::Polygon { blades, .. } = aperture.shape {
+        if blades < 3 {
+            bail!("aperture needs at least 3 blades");
+        }
+    }
 
-    let pb = Arc::new(Mutex::new(ProgressBar::new(u64::from(width * height))));
+    // The shape of the aperture only applies to the thin lens of the perspective projection
+    let thin_lens = prescription.is_none() && matches.value_of("projection") == Some("perspective");
+    let shaping = [
+        "aperture",
+        "blades",
+        "blade-rotation",
+        "aperture-mask",
+        "anamorphic-squeeze",
+    ];
+    if let Some(name) = shaping
+        .iter()
+        .find(|&&name| matches.occurrences_of(name) > 0)
+    {
+        if !thin_lens {
+            bail!(
+                "--{} only applies to the thin lens of the perspective projection",
+                name
+            );
+        }
+    }
+
+    // Only the thin lens and fisheyes have a field of view, which must leave them an image
+    for key in &path.keyframes {
+        if thin_lens && !(key.fov > 0.0 && key.fov < 180.0) {
+            bail!("perspective field of view must lie between 0 and 180 degrees");
+        }
+        if fisheye && !(key.fov > 0.0 && key.fov <= 360.0) {
+            bail!("fisheye field of view must lie between 0 and 360 degrees");
+        }
+    }
+
+    // Camera for an eye at a signed distance to the right of the view, with its own aspect ratio
+    let projection =
+        |key: &Keyframe, eye: Scalar, aspect_ratio: Scalar| -> Result<Arc<dyn Camera>, Error> {
+            let view = View::new(key.lookfrom, key.lookat, Vector3::y());
+            Ok(match matches.value_of("projection") {
+                Some("orthographic") => Arc::new(Orthographic::new(
+                    &view.offset(eye),
+                    value_t_or_exit!(matches.value_of("ortho-height"), Scalar),
+                    aspect_ratio,
+                )),
+                Some("equirectangular") => Arc::new(Equirectangular {
+                    view: view.clone(),
+                    eye_offset: eye,
+                }),
+                Some("cubemap") => Arc::new(CubeMap {
+                    view: view.offset(eye),
+                }),
+                Some("fisheye-equidistant") | Some("fisheye-equisolid") => Arc::new(Fisheye {
+                    view: view.offset(eye),
+                    fov_degrees: key.fov,
+                    aspect_ratio,
+                    mapping: if matches.value_of("projection") == Some("fisheye-equisolid") {
+                        FisheyeMapping::Equisolid
+                    } else {
+                        FisheyeMapping::Equidistant
+                    },
+                }),
+                _ => match &prescription {
+                    Some(surfaces) => Arc::new(RealisticLens::new(
+                        &view.offset(eye),
+                        surfaces.clone(),
+                        film_diagonal,
+                        aspect_ratio,
+                        key.focus_distance,
+                    )?),
+                    None => Arc::new(ThinLens::new(
+                        &view.offset(eye),
+                        key.fov,
+                        aspect_ratio,
+                        aperture.clone(),
+                        key.focus_distance,
+                    )),
+                },
+            })
+        };
+
+    let interocular = value_t_or_exit!(matches.value_of("interocular"), Scalar);
+    let (first, last) = frames.unwrap_or((0, 0));
     let start = Instant::now();
 
-    let img = {
-        let pb = pb.clone();
+    // The scene is built once and shared by every frame of an animation
+    for frame in first..=last {
+        let key = path.at(frame as Scalar);
+        let output = match frames {
+            Some(_) => frame_path(output, frame),
+            None => output.to_string(),
+        };
+        let world = world.clone();
+        let film = film.clone();
 
-        image::Image::from_fn(
-            width as usize,
-            height as usize,
-            move |x, y| -> ::image::Rgba<u8> {
-                let mut c = Color::new(0.0, 0.0, 0.0, 0.0);
+        let camera: Arc<dyn Camera> = match matches.value_of("stereo") {
+            Some("side-by-side") => Arc::new(Stereo {
+                left: projection(&key, -interocular / 2.0, aspect_ratio / 2.0)?,
+                right: projection(&key, interocular / 2.0, aspect_ratio / 2.0)?,
+                layout: StereoLayout::SideBySide,
+            }),
+            Some("top-bottom") => Arc::new(Stereo {
+                left: projection(&key, -interocular / 2.0, aspect_ratio * 2.0)?,
+                right: projection(&key, interocular / 2.0, aspect_ratio * 2.0)?,
+                layout: StereoLayout::TopBottom,
+            }),
+            _ => projection(&key, 0.0, aspect_ratio)?,
+        };
 
-                for _ in 0..samples {
-                    let u = (x as Scalar + random::<Scalar>()) / width as Scalar;
-                    let v = 1.0 - (y as Scalar + random::<Scalar>()) / height as Scalar;
+        if frames.is_some() {
+            info!("Rendering frame {} to {}", frame, output);
+        }
+        let pb = Arc::new(Mutex::new(ProgressBar::new(u64::from(width * height))));
 
-                    let ray = camera.get_ray(u, v);
-                    c += color(&ray, &scene, maxdepth, 0)
-                }
+        let framebuffer = Arc::new(Framebuffer::new(
+            width,
+            height,
+            filter.clone(),
+            transparent_background,
+            outlier_threshold,
+        ));
+        let statistics = Mutex::new(PathStatistics::new(maxdepth));
+        framebuffer.render(|x, y| {
+            let mut pixel = Vec::with_capacity(samples as usize);
+            let mut stats = PathStatistics::new(maxdepth);
 
-                pb.lock().unwrap().inc();
-                c /= samples as Scalar;
+            for i in 0..samples {
+                let px = x as Scalar + random::<Scalar>();
+                let py = y as Scalar + random::<Scalar>();
+                let u = px / width as Scalar;
+                let v = 1.0 - py / height as Scalar;
 
-                c.into()
-            },
-        )
-    };
+                // Samples for which the camera gives no ray still count toward the pixel, as
+                // uncovered black, and those it blocks as covered black
+                let (radiance, albedo, normal) = match camera.get_ray(u, v) {
+                    Some((_, weight)) if weight <= 0.0 => (
+                        Color::new(0.0, 0.0, 0.0, 1.0),
+                        Color::new(0.0, 0.0, 0.0, 1.0),
+                        Vector3::zeros(),
+                    ),
+                    Some((mut ray, weight)) => {
+                        let lambda = film.as_ref().map(|film| film.sample_wavelength(i, samples));
+                        ray.wavelength = lambda;
+                        let (c, albedo, normal) = color(&ray, &world, &integrator, &mut stats);
+                        let c = match (&film, lambda) {
+                            (Some(film), Some(lambda)) => film.to_rgb(&c, lambda),
+                            _ => c,
+                        };
+                        (c * weight, albedo, normal)
+                    }
+                    None => (
+                        Color::new(0.0, 0.0, 0.0, 0.0),
+                        Color::new(0.0, 0.0, 0.0, 1.0),
+                        Vector3::zeros(),
+                    ),
+                };
+                pixel.push(Sample {
+                    x: px,
+                    y: py,
+                    color: radiance,
+                    albedo,
+                    normal,
+                });
+            }
 
-    img.save(output).map_err(Error::from)?;
+            statistics.lock().unwrap().merge(&stats);
+            pb.lock().unwrap().inc();
+            pixel
+        });
 
-    let end = Instant::now();
-    pb.lock().unwrap().finish();
+        let img = match &denoiser {
+            Some(denoiser) => {
+                let pixels = denoiser.denoise(&framebuffer);
+                image::Image::from_fn(
+                    width as usize,
+                    height as usize,
+                    move |x, y| -> ::image::Rgba<u8> { pixels[(y * width + x) as usize].into() },
+                )
+            }
+            None => {
+                let framebuffer = framebuffer.clone();
+                image::Image::from_fn(
+                    width as usize,
+                    height as usize,
+                    move |x, y| -> ::image::Rgba<u8> { framebuffer.pixel(x, y).into() },
+                )
+            }
+        };
 
+        img.save(output).map_err(Error::from)?;
+        pb.lock().unwrap().finish();
+        if matches.is_present("statistics") {
+            statistics.lock().unwrap().log();
+        }
+    }
+
+    let end = Instant::now();
     info!("Finished in {:?}", end.duration_since(start));
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blend::{AlphaMask, TwoSided};
+    use crate::materials::Lambertian;
+    use crate::shapes::Sphere;
+    use crate::textures::Constant;
+
+    /// Fraction of rays meeting the walls of the furnace which scatter off of them
+    const OPACITY: Scalar = 0.5;
+
+    /// Reflectance of the walls of the furnace
+    const ALBEDO: Scalar = 0.8;
+
+    /// Sphere of partly transparent diffuse walls lit evenly from all around, in which paths
+    /// bounce a random number of times before escaping to the environment
+    fn furnace() -> World {
+        World {
+            scene: vec![Arc::new(Sphere {
+                center: Point3::origin(),
+                radius: 1.0,
+                material: Arc::new(TwoSided {
+                    material: Arc::new(AlphaMask {
+                        mask: Arc::new(Constant::scalar(OPACITY)),
+                        material: Arc::new(Lambertian {
+                            albedo: Arc::new(Constant::scalar(ALBEDO)),
+                        }),
+                    }),
+                }),
+            })],
+            fog: None,
+            environment: Arc::new(Gradient {
+                horizon: Color::new(1.0, 1.0, 1.0, 1.0),
+                zenith: Color::new(1.0, 1.0, 1.0, 1.0),
+            }),
+        }
+    }
+
+    fn integrator(maxdepth: u32, max_diffuse: u32, roulette_depth: u32) -> Integrator {
+        Integrator {
+            maxdepth,
+            max_diffuse,
+            max_specular: maxdepth,
+            max_transmission: maxdepth,
+            roulette_depth,
+            clamp_direct: None,
+            clamp_indirect: None,
+        }
+    }
+
+    /// Average radiance reaching the center of the furnace
+    fn mean_radiance(integrator: &Integrator) -> Scalar {
+        let world = furnace();
+        let mut stats = PathStatistics::new(integrator.maxdepth);
+        let count = 200_000;
+        let total: Scalar = (0..count)
+            .map(|_| {
+                let ray = Ray::new(Point3::origin(), Vector3::new(0.3, 0.5, -0.8));
+                color(&ray, &world, integrator, &mut stats).0.g
+            })
+            .sum();
+        total / count as Scalar
+    }
+
+    #[test]
+    fn survival_follows_throughput_past_the_roulette_depth() {
+        let integrator = integrator(64, 64, 3);
+        let mut path = PathState::camera();
+        path.throughput = Color::new(0.2, 0.4, 0.1, 1.0);
+        path.depth = 2;
+        assert_eq!(integrator.survival(&path), 1.0);
+        path.depth = 3;
+        assert_eq!(integrator.survival(&path), 0.4);
+        path.throughput = Color::new(3.0, 0.4, 0.1, 1.0);
+        assert_eq!(integrator.survival(&path), 1.0);
+    }
+
+    #[test]
+    fn roulette_leaves_radiance_unbiased() {
+        // Each meeting with the walls either lets the path escape or scatters it back inside
+        let expected = (1.0 - OPACITY) / (1.0 - OPACITY * ALBEDO);
+        for &roulette_depth in &[64, 1, 0] {
+            let mean = mean_radiance(&integrator(64, 64, roulette_depth));
+            assert!(
+                (mean - expected).abs() < 0.01,
+                "{} {}",
+                roulette_depth,
+                mean
+            );
+        }
+    }
+
+    #[test]
+    fn paths_out_of_bounces_follow_their_last_ray() {
+        // Only paths escaping before reaching the walls or right after scattering off of them
+        let expected = (1.0 - OPACITY) * (1.0 + OPACITY * ALBEDO);
+        let mean = mean_radiance(&integrator(64, 0, 64));
+        assert!((mean - expected).abs() < 0.01, "{}", mean);
+        let mean = mean_radiance(&integrator(1, 64, 64));
+        assert!((mean - expected).abs() < 0.01, "{}", mean);
+    }
+
+    #[test]
+    fn deep_paths_are_followed_without_recursion() {
+        // Light never escapes a closed white sphere, so paths bounce until the maximum depth,
+        // deeper than the stack of the thread would allow a recursive integrator to go
+        let world = World {
+            scene: vec![Arc::new(Sphere {
+                center: Point3::origin(),
+                radius: 1.0,
+                material: Arc::new(TwoSided {
+                    material: Arc::new(Lambertian {
+                        albedo: Arc::new(Constant::scalar(1.0)),
+                    }),
+                }),
+            })],
+            ..furnace()
+        };
+        let maxdepth = 100_000;
+        let integrator = integrator(maxdepth, maxdepth, maxdepth);
+        let mut stats = PathStatistics::new(maxdepth);
+        let ray = Ray::new(Point3::origin(), Vector3::new(0.3, 0.5, -0.8));
+        let (radiance, _, _) = color(&ray, &world, &integrator, &mut stats);
+
+        assert_eq!(radiance.g, 0.0);
+        assert!(stats.bounces.iter().all(|&count| count == 1));
+        assert_eq!(stats.limited, 1);
+    }
+}
@@ -1,7 +1,12 @@
+use std::sync::Arc;
+
 use rand::random;
 
+use crate::media::Interior;
 use crate::shapes::HitResult;
-use crate::types::{Color, Ray, Vector3};
+use crate::spectrum::Dispersion;
+use crate::textures::Texture;
+use crate::types::{Color, Ray, Scalar, Vector3};
 
 /// Sample a random point in the unit sphere via rejection
 fn random_in_unit_sphere() -> Vector3 {
@@ -15,6 +20,11 @@ fn random_in_unit_sphere() -> Vector3 {
     p
 }
 
+/// Sample a random direction uniformly over the unit sphere
+fn random_unit_vector() -> Vector3 {
+    random_in_unit_sphere().normalize()
+}
+
 /// Generate a reflection ray from a surface with the given normal
 fn reflect(v: Vector3, n: Vector3) -> Vector3 {
     v - 2.0 * v.dot(&n) * n
@@ -41,41 +51,120 @@ fn schlick(cosine: f32, ior: f32) -> f32 {
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
 
+/// Kind of interaction by which a ray was scattered, limited separately in depth along a path
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScatterKind {
+    /// Scattering in a broad range of directions, from rough surfaces and particles in volumes
+    Diffuse,
+    /// Reflection in or around the mirror direction
+    Specular,
+    /// Refraction through to the other side of a surface
+    Transmission,
+}
+
 /// A ray scattered via interaction with a surface
 pub struct ScatteredRay {
     pub ray: Ray,
     pub attenuation: Color,
+    pub kind: ScatterKind,
 }
 
 /// Material defines surface properties and generation of scattered rays
 pub trait Material: Send + Sync + std::fmt::Debug {
     /// Calculate scattered ray generated by an incoming ray interacting with this surface
     fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay>;
+
+    /// Light emitted by this surface at the point of intersection
+    fn emitted(&self, hit: &HitResult) -> Color {
+        let _ = hit;
+        Color::new(0.0, 0.0, 0.0, 1.0)
+    }
+
+    /// Color of the surface apart from lighting and whichever lobe scatter() happens to choose,
+    /// guiding the denoiser in keeping textures sharp. Surfaces with no single color, such as
+    /// glass and lights, are white so that the denoiser leaves their light as it is.
+    fn albedo(&self, hit: &HitResult) -> Color {
+        let _ = hit;
+        Color::new(1.0, 1.0, 1.0, 1.0)
+    }
+
+    /// Evaluate the fraction of light arriving from a direction which is scattered along the
+    /// incoming ray, including the cosine term. Returns None for materials which cannot be
+    /// evaluated for arbitrary directions, such as perfect mirrors.
+    fn eval(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Color> {
+        let _ = (ray, hit, direction);
+        None
+    }
+
+    /// Probability density with which scatter() would choose the given direction, available
+    /// whenever eval() is
+    fn pdf(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Scalar> {
+        let _ = (ray, hit, direction);
+        None
+    }
+
+    /// Medium filling the inside of closed shapes made of this material, through which light
+    /// entering the surface scatters before leaving it again
+    fn interior(&self) -> Option<&Interior> {
+        None
+    }
+
+    /// Fraction of rays stopped by this surface rather than passing through it, as for cutouts
+    fn opacity(&self, hit: &HitResult) -> Scalar {
+        let _ = hit;
+        1.0
+    }
 }
 
 /// Lambertian material
 #[derive(Debug, Clone)]
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture>,
 }
 
 impl Material for Lambertian {
     /// Calculate scattered ray generated by an incoming ray interacting with this surface
     fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
         let _ = ray;
-        let target = hit.p.coords + hit.normal + random_in_unit_sphere();
+
+        // Offsetting the normal by a point on the unit sphere gives a cosine distribution
+        let mut direction = hit.normal + random_unit_vector();
+        if direction.magnitude_squared() < 1e-8 {
+            direction = hit.normal;
+        }
+
         Some(ScatteredRay {
-            ray: Ray::new(hit.p, target - hit.p.coords),
-            attenuation: self.albedo,
+            ray: Ray::new(hit.p, direction),
+            attenuation: self.albedo.value(&hit.uv, &hit.p),
+            kind: ScatterKind::Diffuse,
         })
     }
+
+    /// Evaluate the fraction of light arriving from a direction which is scattered along the
+    /// incoming ray, including the cosine term
+    fn eval(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Color> {
+        let _ = ray;
+        let cosine = hit.normal.dot(direction).max(0.0);
+        Some(self.albedo.value(&hit.uv, &hit.p) * (cosine / std::f32::consts::PI))
+    }
+
+    /// Probability density with which scatter() would choose the given direction
+    fn pdf(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Scalar> {
+        let _ = ray;
+        Some(hit.normal.dot(direction).max(0.0) / std::f32::consts::PI)
+    }
+
+    /// Color of the surface apart from lighting, guiding the denoiser
+    fn albedo(&self, hit: &HitResult) -> Color {
+        self.albedo.value(&hit.uv, &hit.p)
+    }
 }
 
 /// Metalic material
 #[derive(Debug, Clone)]
 pub struct Metal {
-    pub albedo: Color,
-    pub roughness: f32,
+    pub albedo: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
 }
 
 impl Material for Metal {
@@ -83,51 +172,68 @@ impl Material for Metal {
     fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
         let reflected = reflect(ray.direction.normalize(), hit.normal);
         if reflected.dot(&hit.normal) > 0.0 {
+            let roughness = self.roughness.scalar(&hit.uv, &hit.p);
             Some(ScatteredRay {
-                ray: Ray::new(hit.p, reflected + self.roughness * random_in_unit_sphere()),
-                attenuation: self.albedo,
+                ray: Ray::new(hit.p, reflected + roughness * random_in_unit_sphere()),
+                attenuation: self.albedo.value(&hit.uv, &hit.p),
+                kind: ScatterKind::Specular,
             })
         } else {
             None
         }
     }
+
+    /// Color of the surface apart from lighting, guiding the denoiser
+    fn albedo(&self, hit: &HitResult) -> Color {
+        self.albedo.value(&hit.uv, &hit.p)
+    }
 }
 
 /// Dialectric material
 #[derive(Debug, Clone)]
 pub struct Dialectric {
-    pub albedo: Color,
-    pub ior: f32,
+    pub albedo: Arc<dyn Texture>,
+    pub ior: Arc<dyn Texture>,
+    /// Variation of the index of refraction with wavelength, used in place of ior for rays
+    /// carrying a wavelength
+    pub dispersion: Option<Dispersion>,
 }
 
 impl Material for Dialectric {
     /// Calculate scattered ray generated by an incoming ray interacting with this surface
     fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
+        let albedo = self.albedo.value(&hit.uv, &hit.p);
+        let ior = match (&self.dispersion, ray.wavelength) {
+            (Some(dispersion), Some(lambda)) => dispersion.ior(lambda),
+            _ => self.ior.scalar(&hit.uv, &hit.p),
+        };
         let reflected = reflect(ray.direction, hit.normal);
         let dot = ray.direction.dot(&hit.normal) / ray.direction.magnitude();
 
         let (outward_normal, ni_over_nt, cosine) = if dot > 0.0 {
             (
                 -hit.normal,
-                self.ior,
-                (1.0 - self.ior * self.ior * (1.0 - dot * dot)).sqrt(),
+                ior,
+                (1.0 - ior * ior * (1.0 - dot * dot)).sqrt(),
             )
         } else {
-            (hit.normal, 1.0 / self.ior, -dot)
+            (hit.normal, 1.0 / ior, -dot)
         };
 
         if let Some(refracted) = refract(ray.direction, outward_normal, ni_over_nt) {
-            if random::<f32>() >= schlick(cosine, self.ior) {
+            if random::<f32>() >= schlick(cosine, ior) {
                 return Some(ScatteredRay {
                     ray: Ray::new(hit.p, refracted),
-                    attenuation: self.albedo,
+                    attenuation: albedo,
+                    kind: ScatterKind::Transmission,
                 });
             }
         }
 
         Some(ScatteredRay {
             ray: Ray::new(hit.p, reflected),
-            attenuation: self.albedo,
+            attenuation: albedo,
+            kind: ScatterKind::Specular,
         })
     }
 }
@@ -0,0 +1,282 @@
+use std::sync::Arc;
+
+use rand::random;
+
+use crate::materials::{Material, ScatterKind, ScatteredRay};
+use crate::shapes::{HitResult, Shape};
+use crate::types::{basis, Color, Point2, Ray, Scalar, Vector3};
+
+/// Phase function describes the angular distribution of light scattered within a medium
+pub trait PhaseFunction: Send + Sync + std::fmt::Debug {
+    /// Sample an outgoing direction for light travelling in the given direction
+    fn sample(&self, direction: &Vector3) -> Vector3;
+}
+
+/// Phase function scattering light equally in all directions
+#[derive(Debug, Clone)]
+pub struct Isotropic;
+
+impl PhaseFunction for Isotropic {
+    /// Sample an outgoing direction for light travelling in the given direction
+    fn sample(&self, direction: &Vector3) -> Vector3 {
+        let _ = direction;
+        let z = 1.0 - 2.0 * random::<Scalar>();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f32::consts::PI * random::<Scalar>();
+        Vector3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+}
+
+/// Henyey-Greenstein phase function, with positive asymmetry favoring forward scattering
+#[derive(Debug, Clone)]
+pub struct HenyeyGreenstein {
+    pub g: Scalar,
+}
+
+impl PhaseFunction for HenyeyGreenstein {
+    /// Sample an outgoing direction for light travelling in the given direction
+    fn sample(&self, direction: &Vector3) -> Vector3 {
+        let g = self.g;
+        let xi = random::<Scalar>();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let sqr = (1.0 - g * g) / (1.0 + g - 2.0 * g * xi);
+            (1.0 + g * g - sqr * sqr) / (2.0 * g)
+        };
+
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * std::f32::consts::PI * random::<Scalar>();
+        let w = direction.normalize();
+        let (u, v) = basis(&w);
+
+        sin_theta * phi.cos() * u + sin_theta * phi.sin() * v + cos_theta * w
+    }
+}
+
+/// Material of the particles suspended in a participating medium
+#[derive(Debug, Clone)]
+pub struct Volumetric {
+    pub albedo: Color,
+    pub phase: Arc<dyn PhaseFunction>,
+}
+
+impl Material for Volumetric {
+    /// Calculate scattered ray generated by an incoming ray interacting with this surface
+    fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
+        Some(ScatteredRay {
+            ray: Ray::new(hit.p, self.phase.sample(&ray.direction)),
+            attenuation: self.albedo,
+            kind: ScatterKind::Diffuse,
+        })
+    }
+
+    /// Color of the surface apart from lighting, guiding the denoiser
+    fn albedo(&self, hit: &HitResult) -> Color {
+        let _ = hit;
+        self.albedo
+    }
+}
+
+/// Sample the distance travelled through a medium of the given density before a scattering event
+fn free_flight(density: Scalar) -> Scalar {
+    -(1.0 - random::<Scalar>()).ln() / density
+}
+
+/// Medium of constant density filling the interior of a closed boundary shape
+#[derive(Clone)]
+pub struct ConstantMedium {
+    pub boundary: Arc<dyn Shape>,
+    pub density: Scalar,
+    pub material: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    /// Determine the span of parameters along a ray lying within the boundary
+    fn span(&self, ray: &Ray, t_min: Scalar, t_max: Scalar) -> Option<(Scalar, Scalar)> {
+        let enter = self.boundary.hit(ray, -Scalar::MAX, Scalar::MAX)?;
+        let exit = self.boundary.hit(ray, enter.t + 0.0001, Scalar::MAX)?;
+
+        let t_enter = enter.t.max(t_min).max(0.0);
+        let t_exit = exit.t.min(t_max);
+        if t_enter >= t_exit {
+            None
+        } else {
+            Some((t_enter, t_exit))
+        }
+    }
+}
+
+impl Shape for ConstantMedium {
+    /// Does an incoming ray intersect this shape
+    fn hit(&self, ray: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitResult> {
+        let (t_enter, t_exit) = self.span(ray, t_min, t_max)?;
+
+        // Ray directions are normalized, so the parameter is also the distance travelled
+        let t = t_enter + free_flight(self.density);
+        if t >= t_exit {
+            return None;
+        }
+
+        Some(HitResult {
+            t,
+            p: ray.at(t),
+            normal: -ray.direction,
+            uv: Point2::origin(),
+            tangent: Vector3::zeros(),
+            bitangent: Vector3::zeros(),
+            material: self.material.clone(),
+        })
+    }
+
+    /// Fraction of light passing through this shape along a ray between t_min and t_max
+    fn transmittance(&self, ray: &Ray, t_min: Scalar, t_max: Scalar) -> Scalar {
+        self.span(ray, t_min, t_max)
+            .map_or(1.0, |(t_enter, t_exit)| {
+                (-self.density * (t_exit - t_enter)).exp()
+            })
+    }
+}
+
+/// Atmospheric fog filling all of space below a given height with a medium of constant density
+#[derive(Debug, Clone)]
+pub struct Fog {
+    pub density: Scalar,
+    pub height: Scalar,
+    pub material: Arc<dyn Material>,
+}
+
+impl Fog {
+    /// Determine the span of parameters along a ray lying below the top of the fog layer
+    fn span(&self, ray: &Ray, t_max: Scalar) -> Option<(Scalar, Scalar)> {
+        let t_top = (self.height - ray.origin.y) / ray.direction.y;
+        let (t_enter, t_exit) = if ray.origin.y <= self.height {
+            (0.0, if t_top > 0.0 { t_top } else { Scalar::MAX })
+        } else if t_top > 0.0 {
+            (t_top, Scalar::MAX)
+        } else {
+            return None;
+        };
+
+        if t_enter >= t_exit.min(t_max) {
+            None
+        } else {
+            Some((t_enter, t_exit.min(t_max)))
+        }
+    }
+
+    /// Determine whether a ray scatters within the fog before reaching a distance of t_max
+    pub fn hit(&self, ray: &Ray, t_max: Scalar) -> Option<HitResult> {
+        let (t_enter, t_exit) = self.span(ray, t_max)?;
+        let t = t_enter + free_flight(self.density);
+        if t >= t_exit {
+            return None;
+        }
+
+        Some(HitResult {
+            t,
+            p: ray.at(t),
+            normal: -ray.direction,
+            uv: Point2::origin(),
+            tangent: Vector3::zeros(),
+            bitangent: Vector3::zeros(),
+            material: self.material.clone(),
+        })
+    }
+
+    /// Fraction of light passing through the fog along a ray up to a distance of t_max
+    pub fn transmittance(&self, ray: &Ray, t_max: Scalar) -> Scalar {
+        self.span(ray, t_max).map_or(1.0, |(t_enter, t_exit)| {
+            (-self.density * (t_exit - t_enter)).exp()
+        })
+    }
+}
+
+/// Most scattering events followed in a random walk through an interior medium
+const MAX_WALK_STEPS: u32 = 4096;
+
+/// Homogeneous medium filling the inside of a closed surface, whose extinction may vary
+/// between channels, followed by a random walk from where light enters to where it leaves
+#[derive(Debug, Clone)]
+pub struct Interior {
+    /// Extinction coefficient per unit distance in each channel
+    pub extinction: Color,
+    /// Fraction of light scattered rather than absorbed at each event, in each channel
+    pub albedo: Color,
+    pub phase: Arc<dyn PhaseFunction>,
+}
+
+impl Interior {
+    /// Follow light travelling inside the medium along a ray toward a surface hit, scattering
+    /// within the medium until it reaches the surface of the shape. Returns the final ray and
+    /// the hit it reaches along with the weight of the walk, or None if the light is absorbed.
+    pub fn walk(
+        &self,
+        scene: &dyn Shape,
+        ray: &Ray,
+        hit: HitResult,
+    ) -> Option<(Ray, HitResult, Color)> {
+        let sigma = [self.extinction.r, self.extinction.g, self.extinction.b];
+        let albedo = [self.albedo.r, self.albedo.g, self.albedo.b];
+        let (mut ray, mut hit) = (ray.clone(), hit);
+
+        // Distances are sampled by the extinction of one channel for the whole walk, weighting
+        // by the density of the path averaged over all channels so that no channel is starved.
+        // Both are kept normalized so that the average path density stays at one.
+        let channel = ((random::<Scalar>() * 3.0) as usize).min(2);
+        let mut weight = [1.0; 3];
+        let mut density = [1.0; 3];
+
+        for _ in 0..MAX_WALK_STEPS {
+            let speed = ray.direction.magnitude();
+            let t = if sigma[channel] > 0.0 {
+                free_flight(sigma[channel]) / speed
+            } else {
+                Scalar::MAX
+            };
+            let scattered = t < hit.t;
+            let distance = t.min(hit.t) * speed;
+
+            for i in 0..3 {
+                let transmittance = (-sigma[i] * distance).exp();
+                if scattered {
+                    weight[i] *= albedo[i] * sigma[i] * transmittance;
+                    density[i] *= sigma[i] * transmittance;
+                } else {
+                    weight[i] *= transmittance;
+                    density[i] *= transmittance;
+                }
+            }
+
+            let average = density.iter().sum::<Scalar>() / 3.0;
+            if average <= 0.0 {
+                return None;
+            }
+            for (w, p) in weight.iter_mut().zip(density.iter_mut()) {
+                *w /= average;
+                *p /= average;
+            }
+
+            if !scattered {
+                let color = Color::new(weight[0], weight[1], weight[2], 1.0);
+                return Some((ray, hit, color));
+            }
+
+            // Russian roulette keeps long walks through bright media from running forever
+            let survival = weight.iter().cloned().fold(0.0, Scalar::max).min(1.0);
+            if random::<Scalar>() >= survival {
+                return None;
+            }
+            for w in weight.iter_mut() {
+                *w /= survival;
+            }
+
+            let mut next = Ray::new(ray.at(t), self.phase.sample(&ray.direction));
+            next.wavelength = ray.wavelength;
+            hit = scene.hit(&next, 0.001, Scalar::MAX)?;
+            ray = next;
+        }
+
+        None
+    }
+}
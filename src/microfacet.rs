@@ -0,0 +1,583 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use rand::random;
+
+use crate::materials::{Material, ScatterKind, ScatteredRay};
+use crate::shapes::HitResult;
+use crate::spectrum::Dispersion;
+use crate::textures::Texture;
+use crate::types::{Color, Ray, Scalar, Vector3};
+
+/// Smallest slope roughness used, below which the distribution is numerically a mirror
+const MIN_ALPHA: Scalar = 1e-3;
+
+/// GGX (Trowbridge-Reitz) distribution of microfacet normals, expressed in a local frame with
+/// the macro surface normal along z and possibly differing roughness along x and y
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    pub alpha_x: Scalar,
+    pub alpha_y: Scalar,
+}
+
+impl Ggx {
+    /// Create a distribution from a perceptual roughness between 0.0 and 1.0, stretched along
+    /// the tangent by an anisotropy between 0.0 and 1.0
+    pub fn new(roughness: Scalar, anisotropy: Scalar) -> Self {
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        let aspect = (1.0 - 0.9 * anisotropy.clamp(0.0, 1.0)).sqrt();
+        Self {
+            alpha_x: (alpha / aspect).max(MIN_ALPHA),
+            alpha_y: (alpha * aspect).max(MIN_ALPHA),
+        }
+    }
+
+    /// Density of microfacets with the given normal
+    pub fn d(&self, h: &Vector3) -> Scalar {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+
+        let e = (h.x / self.alpha_x).powi(2) + (h.y / self.alpha_y).powi(2) + h.z * h.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    /// Smith auxiliary function, measuring microfacet area hidden from a direction
+    fn lambda(&self, w: &Vector3) -> Scalar {
+        if w.z == 0.0 {
+            return Scalar::MAX;
+        }
+
+        let a2 = (self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2);
+        0.5 * ((1.0 + a2 / (w.z * w.z)).sqrt() - 1.0)
+    }
+
+    /// Fraction of microfacets visible from a direction
+    pub fn g1(&self, w: &Vector3) -> Scalar {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of microfacets visible from both of a pair of directions
+    pub fn g(&self, wo: &Vector3, wi: &Vector3) -> Scalar {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Sample a microfacet normal visible from a direction in the upper hemisphere, as
+    /// described by Heitz in "Sampling the GGX Distribution of Visible Normals" (2018)
+    pub fn sample_visible(&self, wo: &Vector3) -> Vector3 {
+        let vh = Vector3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).normalize();
+
+        let lensq = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if lensq > 0.0 {
+            Vector3::new(-vh.y, vh.x, 0.0) / lensq.sqrt()
+        } else {
+            Vector3::x()
+        };
+        let t2 = vh.cross(&t1);
+
+        let r = random::<Scalar>().sqrt();
+        let phi = 2.0 * PI * random::<Scalar>();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+        Vector3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(0.0)).normalize()
+    }
+
+    /// Density of visible normals from a direction, that with which sample_visible() would
+    /// choose the given microfacet normal
+    pub fn pdf_visible(&self, wo: &Vector3, h: &Vector3) -> Scalar {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+
+        self.g1(wo) * wo.dot(h).max(0.0) * self.d(h) / wo.z
+    }
+
+    /// Sample a direction reflected off of a microfacet visible from the outgoing direction,
+    /// returning it along with the normal of the microfacet
+    pub fn sample_reflection(&self, wo: &Vector3) -> Option<(Vector3, Vector3)> {
+        let h = self.sample_visible(wo);
+        let wi = reflect(wo, &h);
+        if wi.z <= 0.0 {
+            None
+        } else {
+            Some((wi, h))
+        }
+    }
+
+    /// Fraction of light reflected between a pair of directions by the microfacets, excluding
+    /// Fresnel and including the cosine term
+    pub fn reflection(&self, wo: &Vector3, wi: &Vector3) -> Scalar {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+
+        let h = (wo + wi).normalize();
+        self.d(&h) * self.g(wo, wi) / (4.0 * wo.z)
+    }
+
+    /// Probability density with which sample_reflection() would choose a direction
+    pub fn pdf_reflection(&self, wo: &Vector3, wi: &Vector3) -> Scalar {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+
+        let h = (wo + wi).normalize();
+        self.pdf_visible(wo, &h) / (4.0 * wo.dot(&h))
+    }
+
+    /// Sample a direction reflected off of or refracted through a visible microfacet of an
+    /// interface between dielectrics, chosen between by Fresnel, where eta is the relative index
+    /// of refraction across the interface
+    pub fn sample_dielectric(&self, wo: &Vector3, eta: Scalar) -> Option<Vector3> {
+        let h = self.sample_visible(wo);
+        if random::<Scalar>() < fresnel_dielectric(wo.dot(&h), eta) {
+            Some(reflect(wo, &h)).filter(|wi| wi.z > 0.0)
+        } else {
+            refract(wo, &h, eta).filter(|wi| wi.z < 0.0)
+        }
+    }
+
+    /// Microfacet normal between a pair of directions on either side of an interface between
+    /// dielectrics, facing up, or None where it is undefined
+    fn dielectric_half_vector(wo: &Vector3, wi: &Vector3, eta: Scalar) -> Option<Vector3> {
+        let h = if wi.z > 0.0 { wo + wi } else { wo + eta * wi };
+        if h.magnitude_squared() < 1e-12 {
+            return None;
+        }
+
+        let h = h.normalize();
+        Some(if h.z < 0.0 { -h } else { h })
+    }
+
+    /// Fraction of light reflected or transmitted between a pair of directions by an interface
+    /// between dielectrics, including Fresnel and the cosine term
+    pub fn dielectric(&self, wo: &Vector3, wi: &Vector3, eta: Scalar) -> Scalar {
+        let h = match Self::dielectric_half_vector(wo, wi, eta) {
+            Some(h) => h,
+            None => return 0.0,
+        };
+        let (cos_o, cos_i) = (wo.dot(&h), wi.dot(&h));
+        let fresnel = fresnel_dielectric(cos_o, eta);
+        let dg = self.d(&h) * self.g(wo, wi);
+
+        if wi.z > 0.0 {
+            fresnel * dg / (4.0 * wo.z)
+        } else if cos_o > 0.0 && cos_i < 0.0 {
+            let denom = cos_o + eta * cos_i;
+            (1.0 - fresnel) * dg * eta * eta * cos_o * -cos_i / (wo.z * denom * denom)
+        } else {
+            0.0
+        }
+    }
+
+    /// Probability density with which sample_dielectric() would choose a direction
+    pub fn pdf_dielectric(&self, wo: &Vector3, wi: &Vector3, eta: Scalar) -> Scalar {
+        let h = match Self::dielectric_half_vector(wo, wi, eta) {
+            Some(h) => h,
+            None => return 0.0,
+        };
+        let (cos_o, cos_i) = (wo.dot(&h), wi.dot(&h));
+        let fresnel = fresnel_dielectric(cos_o, eta);
+        let visible = self.pdf_visible(wo, &h);
+
+        if wi.z > 0.0 {
+            fresnel * visible / (4.0 * cos_o)
+        } else if cos_o > 0.0 && cos_i < 0.0 {
+            let denom = cos_o + eta * cos_i;
+            (1.0 - fresnel) * visible * eta * eta * -cos_i / (denom * denom)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Reflect a direction about a normal, both pointing away from the surface
+pub fn reflect(w: &Vector3, n: &Vector3) -> Vector3 {
+    2.0 * w.dot(n) * n - w
+}
+
+/// Fresnel reflectance of an interface between dielectrics, with eta the ratio of the index of
+/// refraction on the far side of the interface to that on the near side
+pub fn fresnel_dielectric(cos_theta: Scalar, eta: Scalar) -> Scalar {
+    let sin2_t = (1.0 - cos_theta * cos_theta) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_theta - eta * cos_t) / (cos_theta + eta * cos_t);
+    let rp = (eta * cos_theta - cos_t) / (eta * cos_theta + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+/// Refract a direction through a microfacet, both pointing away from the surface, returning
+/// None on total internal reflection
+pub fn refract(w: &Vector3, n: &Vector3, eta: Scalar) -> Option<Vector3> {
+    let cos_i = w.dot(n);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-w / eta + (cos_i / eta - cos_t) * n)
+}
+
+/// Fresnel reflectance of a conductor with complex index of refraction eta + ik, per channel
+fn fresnel_conductor(cos_theta: Scalar, eta: &Color, k: &Color) -> Color {
+    let channel = |eta: Scalar, k: Scalar| {
+        let cos2 = cos_theta * cos_theta;
+        let sin2 = 1.0 - cos2;
+        let (eta2, k2) = (eta * eta, k * k);
+
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos_theta * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+
+        0.5 * (rp + rs)
+    };
+
+    Color::new(
+        channel(eta.r, k.r),
+        channel(eta.g, k.g),
+        channel(eta.b, k.b),
+        1.0,
+    )
+}
+
+/// Complex index of refraction of a metal, sampled at red, green, and blue wavelengths
+#[derive(Debug, Clone, Copy)]
+pub struct ComplexIor {
+    pub eta: Color,
+    pub k: Color,
+}
+
+/// Measured optical constants of gold
+pub const GOLD: ComplexIor = ComplexIor {
+    eta: Color {
+        r: 0.143,
+        g: 0.374,
+        b: 1.442,
+        a: 1.0,
+    },
+    k: Color {
+        r: 3.983,
+        g: 2.386,
+        b: 1.603,
+        a: 1.0,
+    },
+};
+
+/// Measured optical constants of copper
+pub const COPPER: ComplexIor = ComplexIor {
+    eta: Color {
+        r: 0.200,
+        g: 0.924,
+        b: 1.102,
+        a: 1.0,
+    },
+    k: Color {
+        r: 3.912,
+        g: 2.452,
+        b: 2.142,
+        a: 1.0,
+    },
+};
+
+/// Measured optical constants of aluminium
+pub const ALUMINIUM: ComplexIor = ComplexIor {
+    eta: Color {
+        r: 1.657,
+        g: 0.880,
+        b: 0.521,
+        a: 1.0,
+    },
+    k: Color {
+        r: 9.224,
+        g: 6.270,
+        b: 4.837,
+        a: 1.0,
+    },
+};
+
+/// Measured optical constants of silver
+pub const SILVER: ComplexIor = ComplexIor {
+    eta: Color {
+        r: 0.155,
+        g: 0.117,
+        b: 0.138,
+        a: 1.0,
+    },
+    k: Color {
+        r: 4.828,
+        g: 3.122,
+        b: 2.147,
+        a: 1.0,
+    },
+};
+
+/// Rough metal reflecting light off of a GGX distribution of microfacets, with Fresnel
+/// reflectance computed from its complex index of refraction. Anisotropy stretches highlights
+/// along the tangent of the surface, as in brushed metal.
+#[derive(Debug, Clone)]
+pub struct Conductor {
+    pub ior: ComplexIor,
+    pub roughness: Arc<dyn Texture>,
+    pub anisotropy: Scalar,
+}
+
+impl Conductor {
+    /// Microfacet distribution at the point of intersection
+    fn distribution(&self, hit: &HitResult) -> Ggx {
+        Ggx::new(self.roughness.scalar(&hit.uv, &hit.p), self.anisotropy)
+    }
+}
+
+impl Material for Conductor {
+    /// Calculate scattered ray generated by an incoming ray interacting with this surface
+    fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
+        let wo = hit.to_local(&-ray.direction);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let ggx = self.distribution(hit);
+        let (wi, h) = ggx.sample_reflection(&wo)?;
+
+        // Sampling visible normals cancels all but the masking of the reflected direction
+        let fresnel = fresnel_conductor(wo.dot(&h), &self.ior.eta, &self.ior.k);
+        Some(ScatteredRay {
+            ray: Ray::new(hit.p, hit.to_world(&wi)),
+            attenuation: fresnel * (ggx.g(&wo, &wi) / ggx.g1(&wo)),
+            kind: ScatterKind::Specular,
+        })
+    }
+
+    /// Evaluate the fraction of light arriving from a direction which is scattered along the
+    /// incoming ray, including the cosine term
+    fn eval(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Color> {
+        let wo = hit.to_local(&-ray.direction);
+        let wi = hit.to_local(direction);
+        let h = (wo + wi).normalize();
+        let fresnel = fresnel_conductor(wo.dot(&h).max(0.0), &self.ior.eta, &self.ior.k);
+        Some(fresnel * self.distribution(hit).reflection(&wo, &wi))
+    }
+
+    /// Probability density with which scatter() would choose the given direction
+    fn pdf(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Scalar> {
+        let wo = hit.to_local(&-ray.direction);
+        let wi = hit.to_local(direction);
+        Some(self.distribution(hit).pdf_reflection(&wo, &wi))
+    }
+
+    /// Color of the surface apart from lighting, guiding the denoiser
+    fn albedo(&self, hit: &HitResult) -> Color {
+        let _ = hit;
+        fresnel_conductor(1.0, &self.ior.eta, &self.ior.k)
+    }
+}
+
+/// Rough glass scattering light off of and through a GGX distribution of microfacets, split
+/// between reflection and transmission by Fresnel, as described by Walter et al. in
+/// "Microfacet Models for Refraction through Rough Surfaces" (2007). Light travelling inside is
+/// absorbed at a rate given per unit distance in each channel, tinting thicker glass more.
+#[derive(Debug, Clone)]
+pub struct RoughDielectric {
+    pub ior: Arc<dyn Texture>,
+    /// Variation of the index of refraction with wavelength, used in place of ior for rays
+    /// carrying a wavelength
+    pub dispersion: Option<Dispersion>,
+    pub roughness: Arc<dyn Texture>,
+    pub absorption: Color,
+}
+
+/// Outgoing direction in the frame of a hit, flipped to the side of the incoming ray, along
+/// with the relative index of refraction across the interface from that side and the sign
+/// needed to flip other directions likewise
+pub fn orient(ray: &Ray, hit: &HitResult, ior: Scalar) -> (Vector3, Scalar, Scalar) {
+    let wo = hit.to_local(&-ray.direction);
+    if wo.z < 0.0 {
+        (Vector3::new(wo.x, wo.y, -wo.z), 1.0 / ior, -1.0)
+    } else {
+        (wo, ior, 1.0)
+    }
+}
+
+/// Fraction of light surviving a trip of the given length through an absorbing medium
+pub fn beer_lambert(absorption: &Color, distance: Scalar) -> Color {
+    Color::new(
+        (-absorption.r * distance).exp(),
+        (-absorption.g * distance).exp(),
+        (-absorption.b * distance).exp(),
+        1.0,
+    )
+}
+
+impl RoughDielectric {
+    /// Index of refraction at the point of intersection and wavelength of the ray
+    fn ior(&self, ray: &Ray, hit: &HitResult) -> Scalar {
+        match (&self.dispersion, ray.wavelength) {
+            (Some(dispersion), Some(lambda)) => dispersion.ior(lambda),
+            _ => self.ior.scalar(&hit.uv, &hit.p),
+        }
+    }
+
+    /// Fraction of light surviving the trip through the glass to a hit reached from inside
+    fn transmittance(&self, ray: &Ray, hit: &HitResult) -> Color {
+        if ray.direction.dot(&hit.normal) <= 0.0 {
+            Color::new(1.0, 1.0, 1.0, 1.0)
+        } else {
+            beer_lambert(&self.absorption, hit.t)
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    /// Calculate scattered ray generated by an incoming ray interacting with this surface
+    fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
+        let (wo, eta, side) = orient(ray, hit, self.ior(ray, hit));
+        let ggx = Ggx::new(self.roughness.scalar(&hit.uv, &hit.p), 0.0);
+
+        // Choosing between reflection and transmission by Fresnel cancels it from the weight
+        let wi = ggx.sample_dielectric(&wo, eta)?;
+        let wi_world = hit.to_world(&Vector3::new(wi.x, wi.y, wi.z * side));
+        Some(ScatteredRay {
+            ray: Ray::new(hit.p, wi_world),
+            attenuation: self.transmittance(ray, hit) * (ggx.g(&wo, &wi) / ggx.g1(&wo)),
+            kind: if wi.z < 0.0 {
+                ScatterKind::Transmission
+            } else {
+                ScatterKind::Specular
+            },
+        })
+    }
+
+    /// Evaluate the fraction of light arriving from a direction which is scattered along the
+    /// incoming ray, including the cosine term
+    fn eval(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Color> {
+        let (wo, eta, side) = orient(ray, hit, self.ior(ray, hit));
+        let wi = hit.to_local(direction);
+        let wi = Vector3::new(wi.x, wi.y, wi.z * side);
+        let ggx = Ggx::new(self.roughness.scalar(&hit.uv, &hit.p), 0.0);
+        Some(self.transmittance(ray, hit) * ggx.dielectric(&wo, &wi, eta))
+    }
+
+    /// Probability density with which scatter() would choose the given direction
+    fn pdf(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Scalar> {
+        let (wo, eta, side) = orient(ray, hit, self.ior(ray, hit));
+        let wi = hit.to_local(direction);
+        let wi = Vector3::new(wi.x, wi.y, wi.z * side);
+        let ggx = Ggx::new(self.roughness.scalar(&hit.uv, &hit.p), 0.0);
+        Some(ggx.pdf_dielectric(&wo, &wi, eta))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Number of bands of the cosine to the normal over which densities are compared
+    const BANDS: usize = 8;
+
+    /// Integrate a function over the sphere with a midpoint rule in the cosine to the normal and
+    /// the azimuth, returning its integral over each of a number of equal bands of the cosine
+    fn integrate_bands<F: Fn(&Vector3) -> Scalar>(f: F) -> [Scalar; BANDS] {
+        let n = 1024;
+        let mut bands = [0.0; BANDS];
+        for i in 0..n {
+            let z = -1.0 + 2.0 * (i as Scalar + 0.5) / n as Scalar;
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            for j in 0..n {
+                let phi = 2.0 * PI * (j as Scalar + 0.5) / n as Scalar;
+                let w = Vector3::new(r * phi.cos(), r * phi.sin(), z);
+                bands[i * BANDS / n] += f(&w) * 4.0 * PI / (n * n) as Scalar;
+            }
+        }
+        bands
+    }
+
+    /// Fraction of a number of samples falling in each band of the cosine to the normal
+    fn histogram<F: Fn() -> Option<Vector3>>(sample: F) -> [Scalar; BANDS] {
+        let count = 200_000;
+        let mut bands = [0.0; BANDS];
+        for w in (0..count).filter_map(|_| sample()) {
+            let band = ((w.z + 1.0) / 2.0 * BANDS as Scalar) as usize;
+            bands[band.min(BANDS - 1)] += 1.0 / count as Scalar;
+        }
+        bands
+    }
+
+    fn assert_bands_close(observed: &[Scalar; BANDS], expected: &[Scalar; BANDS]) {
+        for (o, e) in observed.iter().zip(expected.iter()) {
+            assert!((o - e).abs() < 0.01, "{:?} != {:?}", observed, expected);
+        }
+    }
+
+    fn outgoing() -> Vector3 {
+        Vector3::new(0.6, 0.2, 0.5).normalize()
+    }
+
+    #[test]
+    fn normals_project_onto_unit_area() {
+        let ggx = Ggx::new(0.6, 0.5);
+        let area: Scalar = integrate_bands(|h| ggx.d(h) * h.z).iter().sum();
+        assert!((area - 1.0).abs() < 1e-2, "{}", area);
+    }
+
+    #[test]
+    fn visible_normal_density_integrates_to_one() {
+        let ggx = Ggx::new(0.6, 0.5);
+        let wo = outgoing();
+        let total: Scalar = integrate_bands(|h| ggx.pdf_visible(&wo, h)).iter().sum();
+        assert!((total - 1.0).abs() < 1e-2, "{}", total);
+    }
+
+    #[test]
+    fn visible_normals_are_sampled_with_their_density() {
+        let ggx = Ggx::new(0.6, 0.5);
+        let wo = outgoing();
+        let expected = integrate_bands(|h| ggx.pdf_visible(&wo, h));
+        let observed = histogram(|| Some(ggx.sample_visible(&wo)));
+        assert_bands_close(&observed, &expected);
+    }
+
+    #[test]
+    fn reflections_are_sampled_with_their_density() {
+        let ggx = Ggx::new(0.6, 0.0);
+        let wo = outgoing();
+        let expected = integrate_bands(|wi| ggx.pdf_reflection(&wo, wi));
+        let observed = histogram(|| ggx.sample_reflection(&wo).map(|(wi, _)| wi));
+        assert_bands_close(&observed, &expected);
+    }
+
+    #[test]
+    fn dielectric_directions_are_sampled_with_their_density() {
+        let ggx = Ggx::new(0.5, 0.0);
+        let wo = outgoing();
+        for &eta in &[1.5, 1.0 / 1.5] {
+            let expected = integrate_bands(|wi| ggx.pdf_dielectric(&wo, wi, eta));
+            let observed = histogram(|| ggx.sample_dielectric(&wo, eta));
+            assert_bands_close(&observed, &expected);
+        }
+    }
+
+    #[test]
+    fn dielectric_interface_does_not_create_energy() {
+        let ggx = Ggx::new(0.5, 0.0);
+        let wo = outgoing();
+        let total: Scalar = integrate_bands(|wi| ggx.dielectric(&wo, wi, 1.5))
+            .iter()
+            .sum();
+        assert!(total > 0.5 && total <= 1.01, "{}", total);
+    }
+}
@@ -0,0 +1,263 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::types::{Point3, Scalar, Vector3};
+
+/// Number of entries in the permutation and gradient tables
+const TABLE_SIZE: usize = 256;
+
+/// Hermite smoothing used to interpolate between lattice points
+fn fade(t: Scalar) -> Scalar {
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Generate a random permutation of table indices
+fn permutation(rng: &mut StdRng) -> Vec<usize> {
+    let mut perm: Vec<usize> = (0..TABLE_SIZE).collect();
+    perm.shuffle(rng);
+    perm
+}
+
+/// Noise function defines a smoothly varying pseudorandom field over space
+pub trait NoiseFunction: Send + Sync + std::fmt::Debug {
+    /// Evaluate noise at a point, returning a value roughly between -1.0 and 1.0
+    fn noise(&self, p: &Point3) -> Scalar;
+}
+
+/// Gradient noise on an integer lattice, as described by Ken Perlin
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    gradients: Vec<Vector3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    /// Create a new noise generator, deterministic for a given seed
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let gradients = (0..TABLE_SIZE)
+            .map(|_| {
+                Vector3::new(
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                )
+                .normalize()
+            })
+            .collect();
+
+        Self {
+            gradients,
+            perm_x: permutation(&mut rng),
+            perm_y: permutation(&mut rng),
+            perm_z: permutation(&mut rng),
+        }
+    }
+}
+
+impl NoiseFunction for Perlin {
+    /// Evaluate noise at a point, returning a value roughly between -1.0 and 1.0
+    fn noise(&self, p: &Point3) -> Scalar {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - fx, p.y - fy, p.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+        let (uu, vv, ww) = (fade(u), fade(v), fade(w));
+
+        let mut sum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[((i + di) & 255) as usize]
+                        ^ self.perm_y[((j + dj) & 255) as usize]
+                        ^ self.perm_z[((k + dk) & 255) as usize];
+                    let offset = Vector3::new(u - di as Scalar, v - dj as Scalar, w - dk as Scalar);
+                    let weight = (if di == 1 { uu } else { 1.0 - uu })
+                        * (if dj == 1 { vv } else { 1.0 - vv })
+                        * (if dk == 1 { ww } else { 1.0 - ww });
+                    sum += weight * self.gradients[index].dot(&offset);
+                }
+            }
+        }
+
+        sum
+    }
+}
+
+/// Gradient directions toward the edges of a cube, used by simplex noise
+const SIMPLEX_GRADIENTS: [[Scalar; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+/// Gradient noise on a simplex lattice, cheaper than and free of the axis aligned artifacts of
+/// Perlin noise
+#[derive(Debug, Clone)]
+pub struct Simplex {
+    perm: Vec<usize>,
+}
+
+impl Simplex {
+    /// Create a new noise generator, deterministic for a given seed
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let perm = permutation(&mut rng);
+
+        Self {
+            perm: perm.iter().chain(perm.iter()).cloned().collect(),
+        }
+    }
+}
+
+impl NoiseFunction for Simplex {
+    /// Evaluate noise at a point, returning a value roughly between -1.0 and 1.0
+    fn noise(&self, p: &Point3) -> Scalar {
+        const F3: Scalar = 1.0 / 3.0;
+        const G3: Scalar = 1.0 / 6.0;
+
+        // Skew space to find the simplex cell containing the point
+        let s = (p.x + p.y + p.z) * F3;
+        let (i, j, k) = ((p.x + s).floor(), (p.y + s).floor(), (p.z + s).floor());
+        let t = (i + j + k) * G3;
+        let x0 = Vector3::new(p.x - (i - t), p.y - (j - t), p.z - (k - t));
+
+        // Determine which of the six tetrahedra within the cell the point lies in
+        let (o1, o2) = if x0.x >= x0.y {
+            if x0.y >= x0.z {
+                ([1, 0, 0], [1, 1, 0])
+            } else if x0.x >= x0.z {
+                ([1, 0, 0], [1, 0, 1])
+            } else {
+                ([0, 0, 1], [1, 0, 1])
+            }
+        } else if x0.y < x0.z {
+            ([0, 0, 1], [0, 1, 1])
+        } else if x0.x < x0.z {
+            ([0, 1, 0], [0, 1, 1])
+        } else {
+            ([0, 1, 0], [1, 1, 0])
+        };
+
+        let corners = [[0, 0, 0], o1, o2, [1, 1, 1]];
+        let (ii, jj, kk) = (
+            (i as i64 & 255) as usize,
+            (j as i64 & 255) as usize,
+            (k as i64 & 255) as usize,
+        );
+
+        let mut sum = 0.0;
+        for (n, corner) in corners.iter().enumerate() {
+            let offset =
+                x0 - Vector3::new(
+                    corner[0] as Scalar,
+                    corner[1] as Scalar,
+                    corner[2] as Scalar,
+                ) + Vector3::new(1.0, 1.0, 1.0) * (n as Scalar * G3);
+            let falloff = 0.6 - offset.magnitude_squared();
+            if falloff > 0.0 {
+                let index = self.perm
+                    [ii + corner[0] + self.perm[jj + corner[1] + self.perm[kk + corner[2]]]]
+                    % 12;
+                let g = SIMPLEX_GRADIENTS[index];
+                let dot = g[0] * offset.x + g[1] * offset.y + g[2] * offset.z;
+                sum += falloff.powi(4) * dot;
+            }
+        }
+
+        32.0 * sum
+    }
+}
+
+/// Mix the bits of an integer, used to hash lattice cells into pseudorandom values
+fn hash(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Cellular noise giving the distance to the nearest of a set of randomly scattered points,
+/// as described by Steven Worley
+#[derive(Debug, Clone)]
+pub struct Worley {
+    pub seed: u64,
+}
+
+impl Worley {
+    /// Position of the feature point within a lattice cell
+    fn feature(&self, i: i64, j: i64, k: i64) -> Vector3 {
+        let h = hash(
+            self.seed
+                ^ (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+                ^ (j as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+                ^ (k as u64).wrapping_mul(0x1656_67b1_9e37_79f9),
+        );
+        let unit = |bits: u64| (bits & 0x1f_ffff) as Scalar / 0x1f_ffff as Scalar;
+
+        Vector3::new(
+            i as Scalar + unit(h),
+            j as Scalar + unit(h >> 21),
+            k as Scalar + unit(h >> 42),
+        )
+    }
+}
+
+impl NoiseFunction for Worley {
+    /// Evaluate noise at a point, returning a value roughly between -1.0 and 1.0
+    fn noise(&self, p: &Point3) -> Scalar {
+        let (i, j, k) = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+
+        let mut nearest = Scalar::MAX;
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let feature = self.feature(i + di, j + dj, k + dk);
+                    nearest = nearest.min((feature - p.coords).magnitude());
+                }
+            }
+        }
+
+        2.0 * nearest.min(1.0) - 1.0
+    }
+}
+
+/// Fractal Brownian motion, summing octaves of noise at doubling frequency and halving amplitude
+pub fn fbm(noise: &dyn NoiseFunction, p: &Point3, octaves: u32) -> Scalar {
+    let mut sum = 0.0;
+    let mut amplitude = 0.5;
+    let mut q = *p;
+
+    for _ in 0..octaves {
+        sum += amplitude * noise.noise(&q);
+        amplitude *= 0.5;
+        q *= 2.0;
+    }
+
+    sum
+}
+
+/// Turbulence, summing the absolute value of octaves of noise for billowing, creased patterns
+pub fn turbulence(noise: &dyn NoiseFunction, p: &Point3, octaves: u32) -> Scalar {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut q = *p;
+
+    for _ in 0..octaves {
+        sum += amplitude * noise.noise(&q).abs();
+        amplitude *= 0.5;
+        q *= 2.0;
+    }
+
+    sum
+}
@@ -0,0 +1,224 @@
+use std::sync::Arc;
+
+use crate::materials::{Material, ScatteredRay};
+use crate::media::Interior;
+use crate::shapes::HitResult;
+use crate::textures::Texture;
+use crate::types::{Color, Point2, Ray, Scalar, Vector3};
+
+/// Step in surface coordinates used to estimate derivatives of a height map
+const BUMP_DELTA: Scalar = 1e-3;
+
+/// Copy of a hit with its shading normal replaced, keeping the tangents perpendicular to it
+fn perturb(hit: &HitResult, normal: Vector3) -> HitResult {
+    let mut shaded = hit.clone();
+    shaded.normal = normal;
+    shaded.tangent = hit.tangent - normal * normal.dot(&hit.tangent);
+    shaded.bitangent = hit.bitangent - normal * normal.dot(&hit.bitangent);
+    shaded
+}
+
+/// Material perturbing the shading normal of another material with a tangent space normal
+/// map, whose red, green, and blue channels encode the tangent, bitangent and normal axes
+#[derive(Debug, Clone)]
+pub struct NormalMap {
+    pub normals: Arc<dyn Texture>,
+    pub strength: Scalar,
+    pub material: Arc<dyn Material>,
+}
+
+impl NormalMap {
+    /// Hit as seen by the underlying material, with the normal taken from the map
+    fn shade(&self, hit: &HitResult) -> HitResult {
+        let (t, b) = hit.frame();
+        let c = self.normals.value(&hit.uv, &hit.p);
+        let x = (2.0 * c.r - 1.0) * self.strength;
+        let y = (2.0 * c.g - 1.0) * self.strength;
+        let z = (2.0 * c.b - 1.0).max(0.0);
+
+        let normal = t * x + b * y + hit.normal * z;
+        if normal.magnitude_squared() > 1e-12 {
+            perturb(hit, normal.normalize())
+        } else {
+            hit.clone()
+        }
+    }
+}
+
+impl Material for NormalMap {
+    /// Calculate scattered ray generated by an incoming ray interacting with this surface
+    fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
+        self.material.scatter(ray, &self.shade(hit))
+    }
+
+    /// Light emitted by this surface at the point of intersection
+    fn emitted(&self, hit: &HitResult) -> Color {
+        self.material.emitted(&self.shade(hit))
+    }
+
+    /// Evaluate the fraction of light arriving from a direction which is scattered along the
+    /// incoming ray, including the cosine term
+    fn eval(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Color> {
+        self.material.eval(ray, &self.shade(hit), direction)
+    }
+
+    /// Probability density with which scatter() would choose the given direction
+    fn pdf(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Scalar> {
+        self.material.pdf(ray, &self.shade(hit), direction)
+    }
+
+    /// Color of the surface apart from lighting, guiding the denoiser
+    fn albedo(&self, hit: &HitResult) -> Color {
+        self.material.albedo(&self.shade(hit))
+    }
+
+    /// Medium filling the inside of closed shapes made of the underlying material
+    fn interior(&self) -> Option<&Interior> {
+        self.material.interior()
+    }
+
+    /// Fraction of rays stopped by this surface rather than passing through it
+    fn opacity(&self, hit: &HitResult) -> Scalar {
+        self.material.opacity(hit)
+    }
+}
+
+/// Material perturbing the shading normal of another material as if its surface were displaced
+/// along the normal by a height map, scaled to world units
+#[derive(Debug, Clone)]
+pub struct BumpMap {
+    pub height: Arc<dyn Texture>,
+    pub scale: Scalar,
+    pub material: Arc<dyn Material>,
+}
+
+impl BumpMap {
+    /// Hit as seen by the underlying material, with the normal of the displaced surface
+    fn shade(&self, hit: &HitResult) -> HitResult {
+        // Finite differences of height, shifting both surface coordinates and position so that
+        // image and solid textures alike are differentiated
+        let height = |du: Scalar, dv: Scalar| {
+            let uv = Point2::new(hit.uv.x + du, hit.uv.y + dv);
+            let p = hit.p + hit.tangent * du + hit.bitangent * dv;
+            self.scale * self.height.scalar(&uv, &p)
+        };
+        let h = height(0.0, 0.0);
+        let dhdu = (height(BUMP_DELTA, 0.0) - h) / BUMP_DELTA;
+        let dhdv = (height(0.0, BUMP_DELTA) - h) / BUMP_DELTA;
+
+        let dpdu = hit.tangent + hit.normal * dhdu;
+        let dpdv = hit.bitangent + hit.normal * dhdv;
+        let normal = dpdu.cross(&dpdv);
+        if normal.magnitude_squared() <= 1e-12 {
+            return hit.clone();
+        }
+
+        let normal = normal.normalize();
+        if normal.dot(&hit.normal) < 0.0 {
+            perturb(hit, -normal)
+        } else {
+            perturb(hit, normal)
+        }
+    }
+}
+
+impl Material for BumpMap {
+    /// Calculate scattered ray generated by an incoming ray interacting with this surface
+    fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
+        self.material.scatter(ray, &self.shade(hit))
+    }
+
+    /// Light emitted by this surface at the point of intersection
+    fn emitted(&self, hit: &HitResult) -> Color {
+        self.material.emitted(&self.shade(hit))
+    }
+
+    /// Evaluate the fraction of light arriving from a direction which is scattered along the
+    /// incoming ray, including the cosine term
+    fn eval(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Color> {
+        self.material.eval(ray, &self.shade(hit), direction)
+    }
+
+    /// Probability density with which scatter() would choose the given direction
+    fn pdf(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Scalar> {
+        self.material.pdf(ray, &self.shade(hit), direction)
+    }
+
+    /// Color of the surface apart from lighting, guiding the denoiser
+    fn albedo(&self, hit: &HitResult) -> Color {
+        self.material.albedo(&self.shade(hit))
+    }
+
+    /// Medium filling the inside of closed shapes made of the underlying material
+    fn interior(&self) -> Option<&Interior> {
+        self.material.interior()
+    }
+
+    /// Fraction of rays stopped by this surface rather than passing through it
+    fn opacity(&self, hit: &HitResult) -> Scalar {
+        self.material.opacity(hit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blend::AlphaMask;
+    use crate::materials::Lambertian;
+    use crate::subsurface::Subsurface;
+    use crate::textures::Constant;
+    use crate::types::Point3;
+
+    fn hit(material: Arc<dyn Material>) -> HitResult {
+        HitResult {
+            t: 1.0,
+            p: Point3::origin(),
+            normal: Vector3::y(),
+            uv: Point2::new(0.5, 0.5),
+            tangent: Vector3::x(),
+            bitangent: -Vector3::z(),
+            material,
+        }
+    }
+
+    #[test]
+    fn forwards_the_interior_of_the_underlying_material() {
+        let subsurface = Arc::new(Subsurface::new(
+            Color::new(0.8, 0.5, 0.4, 1.0),
+            Color::new(0.1, 0.1, 0.1, 1.0),
+            1.4,
+            Arc::new(Constant::scalar(0.3)),
+            0.0,
+        ));
+        let mapped = NormalMap {
+            normals: Arc::new(Constant {
+                color: Color::new(0.5, 0.5, 1.0, 1.0),
+            }),
+            strength: 1.0,
+            material: subsurface.clone(),
+        };
+        let bumped = BumpMap {
+            height: Arc::new(Constant::scalar(0.0)),
+            scale: 1.0,
+            material: subsurface,
+        };
+        assert!(mapped.interior().is_some());
+        assert!(bumped.interior().is_some());
+    }
+
+    #[test]
+    fn forwards_the_opacity_of_the_underlying_material() {
+        let cutout: Arc<dyn Material> = Arc::new(AlphaMask {
+            mask: Arc::new(Constant::scalar(0.25)),
+            material: Arc::new(Lambertian {
+                albedo: Arc::new(Constant::scalar(0.5)),
+            }),
+        });
+        let bumped = BumpMap {
+            height: Arc::new(Constant::scalar(0.0)),
+            scale: 1.0,
+            material: cutout.clone(),
+        };
+        assert_eq!(bumped.opacity(&hit(cutout)), 0.25);
+    }
+}
@@ -0,0 +1,251 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use rand::random;
+
+use crate::materials::{Material, ScatterKind, ScatteredRay};
+use crate::microfacet::{orient, Ggx};
+use crate::shapes::HitResult;
+use crate::textures::Texture;
+use crate::types::{Color, Ray, Scalar, Vector3};
+
+/// Schlick's approximation of the Fresnel weight at a given angle
+fn schlick_weight(cos_theta: Scalar) -> Scalar {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+/// Linearly interpolate between two colors
+fn mix(a: Color, b: Color, t: Scalar) -> Color {
+    a * (1.0 - t) + b * t
+}
+
+/// Sample a direction in the upper hemisphere of a local frame with a cosine distribution
+fn cosine_hemisphere() -> Vector3 {
+    let r = random::<Scalar>().sqrt();
+    let phi = 2.0 * PI * random::<Scalar>();
+    Vector3::new(r * phi.cos(), r * phi.sin(), (1.0 - r * r).max(0.0).sqrt())
+}
+
+/// Principled material in the style of Disney's BRDF and the glTF metallic-roughness model,
+/// layering a diffuse base with sheen, specular reflection, specular transmission, and a
+/// clearcoat. Parameters other than the index of refraction run from 0.0 to 1.0, with a
+/// specular of 0.5 giving the 4% reflectance typical of dielectrics. Light reflected by the
+/// specular lobe is taken from that reaching the diffuse lobe, and transmission replaces both.
+#[derive(Debug, Clone)]
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    pub specular: Scalar,
+    pub sheen: Scalar,
+    pub sheen_tint: Scalar,
+    pub clearcoat: Scalar,
+    pub clearcoat_roughness: Scalar,
+    pub transmission: Scalar,
+    pub ior: Arc<dyn Texture>,
+}
+
+/// Lobes of a principled material evaluated at a point on its surface
+struct Lobes {
+    base: Color,
+    roughness: Scalar,
+    f0: Color,
+    sheen: Color,
+    specular: Ggx,
+    clearcoat: Ggx,
+    ior: Scalar,
+    /// Weights of the diffuse, specular, transmission, and clearcoat lobes
+    weights: [Scalar; 4],
+    /// Probabilities of sampling each lobe
+    probabilities: [Scalar; 4],
+}
+
+impl Lobes {
+    /// Fraction of light arriving from a direction scattered along an outgoing direction, both
+    /// in the local frame with the outgoing direction above the surface, including the cosine
+    fn eval(&self, wo: &Vector3, wi: &Vector3) -> Color {
+        // Only light refracted into the material is tinted by its base color
+        let glass = self.weights[2] * self.specular.dielectric(wo, wi, self.ior);
+        if wi.z <= 0.0 {
+            return self.base * glass;
+        }
+        let mut f = Color::new(glass, glass, glass, 1.0);
+
+        let h = (wo + wi).normalize();
+        let cos_d = wi.dot(&h);
+        let white = Color::new(1.0, 1.0, 1.0, 1.0);
+        let fresnel = mix(self.f0, white, schlick_weight(cos_d));
+
+        // Diffuse with retroreflection at grazing angles, lit only by light the specular lobe
+        // does not reflect, plus sheen
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+        let fd = (1.0 + (fd90 - 1.0) * schlick_weight(wo.z))
+            * (1.0 + (fd90 - 1.0) * schlick_weight(wi.z));
+        let diffuse =
+            self.base * (white - fresnel) * (fd / PI) + self.sheen * schlick_weight(cos_d);
+        f += diffuse * (self.weights[0] * wi.z);
+
+        f += fresnel * (self.weights[1] * self.specular.reflection(wo, wi));
+
+        let coat_fresnel = 0.04 + 0.96 * schlick_weight(cos_d);
+        f + Color::new(1.0, 1.0, 1.0, 1.0)
+            * (self.weights[3] * 0.25 * coat_fresnel * self.clearcoat.reflection(wo, wi))
+    }
+
+    /// Probability density with which sample() would choose a direction
+    fn pdf(&self, wo: &Vector3, wi: &Vector3) -> Scalar {
+        let p = &self.probabilities;
+        p[0] * wi.z.max(0.0) / PI
+            + p[1] * self.specular.pdf_reflection(wo, wi)
+            + p[2] * self.specular.pdf_dielectric(wo, wi, self.ior)
+            + p[3] * self.clearcoat.pdf_reflection(wo, wi)
+    }
+
+    /// Sample an incoming direction from one of the lobes
+    fn sample(&self, wo: &Vector3) -> Option<(Vector3, ScatterKind)> {
+        let p = &self.probabilities;
+        let u = random::<Scalar>();
+        if u < p[0] {
+            Some((cosine_hemisphere(), ScatterKind::Diffuse))
+        } else if u < p[0] + p[1] {
+            self.specular
+                .sample_reflection(wo)
+                .map(|(wi, _)| (wi, ScatterKind::Specular))
+        } else if u < p[0] + p[1] + p[2] {
+            self.specular.sample_dielectric(wo, self.ior).map(|wi| {
+                if wi.z < 0.0 {
+                    (wi, ScatterKind::Transmission)
+                } else {
+                    (wi, ScatterKind::Specular)
+                }
+            })
+        } else {
+            self.clearcoat
+                .sample_reflection(wo)
+                .map(|(wi, _)| (wi, ScatterKind::Specular))
+        }
+    }
+}
+
+impl Principled {
+    /// Evaluate the parameters of the material at the point of intersection
+    fn lobes(&self, hit: &HitResult) -> Lobes {
+        let base = self.base_color.value(&hit.uv, &hit.p);
+        let metallic = self.metallic.scalar(&hit.uv, &hit.p).clamp(0.0, 1.0);
+        let roughness = self.roughness.scalar(&hit.uv, &hit.p).clamp(0.0, 1.0);
+        let white = Color::new(1.0, 1.0, 1.0, 1.0);
+
+        let tint = if base.luminance() > 0.0 {
+            base / base.luminance()
+        } else {
+            white
+        };
+        let ior = self.ior.scalar(&hit.uv, &hit.p);
+
+        // Transmission takes the place of the diffuse and specular lobes of the dielectric part,
+        // whose glass lobe reflects light by itself
+        let dielectric = 1.0 - metallic;
+        let transmission = self.transmission.clamp(0.0, 1.0);
+        let weights = [
+            dielectric * (1.0 - transmission),
+            metallic + dielectric * (1.0 - transmission),
+            dielectric * transmission,
+            self.clearcoat,
+        ];
+
+        // Sample dielectric specular reflection less often, as it reflects little light
+        let importance = [
+            weights[0],
+            weights[1] * (0.25 + 0.75 * metallic),
+            weights[2],
+            weights[3] * 0.25,
+        ];
+        let total: Scalar = importance.iter().sum();
+        let mut probabilities = [0.0; 4];
+        for (p, w) in probabilities.iter_mut().zip(importance.iter()) {
+            *p = w / total;
+        }
+
+        Lobes {
+            base,
+            roughness,
+            f0: mix(white * (0.08 * self.specular), base, metallic),
+            sheen: mix(white, tint, self.sheen_tint) * self.sheen,
+            specular: Ggx::new(roughness, 0.0),
+            clearcoat: Ggx::new(self.clearcoat_roughness, 0.0),
+            ior,
+            weights,
+            probabilities,
+        }
+    }
+}
+
+impl Material for Principled {
+    /// Calculate scattered ray generated by an incoming ray interacting with this surface
+    fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
+        let lobes = self.lobes(hit);
+        let (wo, eta, side) = orient(ray, hit, lobes.ior);
+
+        // Light inside the material can only have arrived by transmission
+        if side < 0.0 {
+            let wi = lobes.specular.sample_dielectric(&wo, eta)?;
+            let weight = lobes.specular.g(&wo, &wi) / lobes.specular.g1(&wo);
+            return Some(ScatteredRay {
+                ray: Ray::new(hit.p, hit.to_world(&Vector3::new(wi.x, wi.y, -wi.z))),
+                attenuation: Color::new(weight, weight, weight, 1.0),
+                kind: if wi.z < 0.0 {
+                    ScatterKind::Transmission
+                } else {
+                    ScatterKind::Specular
+                },
+            });
+        }
+
+        let (wi, kind) = lobes.sample(&wo)?;
+        let pdf = lobes.pdf(&wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(ScatteredRay {
+            ray: Ray::new(hit.p, hit.to_world(&wi)),
+            attenuation: lobes.eval(&wo, &wi) / pdf,
+            kind,
+        })
+    }
+
+    /// Evaluate the fraction of light arriving from a direction which is scattered along the
+    /// incoming ray, including the cosine term
+    fn eval(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Color> {
+        let lobes = self.lobes(hit);
+        let (wo, eta, side) = orient(ray, hit, lobes.ior);
+        let wi = hit.to_local(direction);
+        let wi = Vector3::new(wi.x, wi.y, wi.z * side);
+
+        if side < 0.0 {
+            let f = lobes.specular.dielectric(&wo, &wi, eta);
+            return Some(Color::new(f, f, f, 1.0));
+        }
+
+        Some(lobes.eval(&wo, &wi))
+    }
+
+    /// Probability density with which scatter() would choose the given direction
+    fn pdf(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Scalar> {
+        let lobes = self.lobes(hit);
+        let (wo, eta, side) = orient(ray, hit, lobes.ior);
+        let wi = hit.to_local(direction);
+        let wi = Vector3::new(wi.x, wi.y, wi.z * side);
+
+        if side < 0.0 {
+            return Some(lobes.specular.pdf_dielectric(&wo, &wi, eta));
+        }
+
+        Some(lobes.pdf(&wo, &wi))
+    }
+
+    /// Color of the surface apart from lighting, guiding the denoiser
+    fn albedo(&self, hit: &HitResult) -> Color {
+        self.base_color.value(&hit.uv, &hit.p)
+    }
+}
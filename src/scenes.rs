@@ -0,0 +1,1078 @@
+use std::sync::Arc;
+
+use rand::random;
+
+use crate::blend::{AlphaMask, Mix, TwoSided};
+use crate::coatings::{Layered, ThinFilm};
+use crate::materials::{Dialectric, Lambertian, Metal};
+use crate::media::{ConstantMedium, HenyeyGreenstein, Isotropic, Volumetric};
+use crate::microfacet::{Conductor, RoughDielectric, ALUMINIUM, COPPER, GOLD, SILVER};
+use crate::noise::{Perlin, Simplex, Worley};
+use crate::normals::{BumpMap, NormalMap};
+use crate::principled::Principled;
+use crate::shapes::{Scene, Sphere};
+use crate::spectrum::{Dispersion, BK7, SF11};
+use crate::subsurface::Subsurface;
+use crate::textures::{Checker, Constant, ImageTexture, Noise, Pattern, Texture};
+use crate::types::{Color, Point3, Scalar, Vector3};
+use crate::voxels::{GridMaterial, GridMedium, VoxelGrid};
+
+/// Generate a random scene containing spheres of various sizes and materials
+pub fn random_spheres() -> Scene {
+    let mut scene: Scene = vec![];
+    scene.push(Arc::new(Sphere {
+        center: Point3::new(0.0, -1000.0, 0.0),
+        radius: 1000.0,
+        material: Arc::new(Lambertian {
+            albedo: Arc::new(Constant {
+                color: Color::new(0.5, 0.5, 0.5, 1.0),
+            }),
+        }),
+    }));
+
+    let avoid = Vector3::new(4.0, 0.2, 0.0);
+    for a in -11..11 {
+        for b in -11..11 {
+            let center = Point3::new(
+                (a as Scalar) + 0.9 * random::<Scalar>(),
+                0.2,
+                (b as Scalar) + 0.9 * random::<Scalar>(),
+            );
+            let choose_mat = random::<Scalar>();
+
+            if (center - avoid).coords.magnitude() > 0.9 {
+                if choose_mat < 0.8 {
+                    let albedo = Color::new(
+                        random::<Scalar>() * random::<Scalar>(),
+                        random::<Scalar>() * random::<Scalar>(),
+                        random::<Scalar>() * random::<Scalar>(),
+                        1.0,
+                    );
+
+                    scene.push(Arc::new(Sphere {
+                        center,
+                        radius: 0.2,
+                        material: Arc::new(Lambertian {
+                            albedo: Arc::new(Constant { color: albedo }),
+                        }),
+                    }));
+                } else if choose_mat < 0.95 {
+                    let albedo = Color::new(
+                        0.5 * (1.0 + random::<Scalar>()),
+                        0.5 * (1.0 + random::<Scalar>()),
+                        0.5 * (1.0 + random::<Scalar>()),
+                        1.0,
+                    );
+                    let roughness = 0.5 * random::<Scalar>();
+
+                    scene.push(Arc::new(Sphere {
+                        center,
+                        radius: 0.2,
+                        material: Arc::new(Metal {
+                            albedo: Arc::new(Constant { color: albedo }),
+                            roughness: Arc::new(Constant::scalar(roughness)),
+                        }),
+                    }));
+                } else {
+                    let albedo = Color::new(1.0, 1.0, 1.0, 1.0);
+                    let ior = 1.5;
+
+                    scene.push(Arc::new(Sphere {
+                        center,
+                        radius: 0.2,
+                        material: Arc::new(Dialectric {
+                            albedo: Arc::new(Constant { color: albedo }),
+                            ior: Arc::new(Constant::scalar(ior)),
+                            dispersion: None,
+                        }),
+                    }));
+                }
+            }
+        }
+    }
+
+    scene.push(Arc::new(Sphere {
+        center: Point3::new(0.0, 1.0, 0.0),
+        radius: 1.0,
+        material: Arc::new(Dialectric {
+            albedo: Arc::new(Constant {
+                color: Color::new(1.0, 1.0, 1.0, 1.0),
+            }),
+            ior: Arc::new(Constant::scalar(1.5)),
+            dispersion: None,
+        }),
+    }));
+
+    scene.push(Arc::new(Sphere {
+        center: Point3::new(-4.0, 1.0, 0.0),
+        radius: 1.0,
+        material: Arc::new(Lambertian {
+            albedo: Arc::new(Constant {
+                color: Color::new(0.4, 0.2, 0.1, 1.0),
+            }),
+        }),
+    }));
+
+    scene.push(Arc::new(Sphere {
+        center: Point3::new(4.0, 1.0, 0.0),
+        radius: 1.0,
+        material: Arc::new(Metal {
+            albedo: Arc::new(Constant {
+                color: Color::new(0.7, 0.6, 0.5, 1.0),
+            }),
+            roughness: Arc::new(Constant::scalar(0.0)),
+        }),
+    }));
+
+    scene
+}
+
+/// Generate a rising plume of fire and smoke in a voxel grid
+fn plume() -> VoxelGrid {
+    VoxelGrid::from_fn([48, 64, 48], |q| {
+        let wobble = 0.06 * (14.0 * q.y + 5.0 * q.z).sin() * (9.0 * q.x + 3.0 * q.y).cos();
+        let radius = 0.1 + 0.3 * q.y;
+        let r = ((q.x - 0.5 + wobble).powi(2) + (q.z - 0.5 - wobble).powi(2)).sqrt();
+        let falloff = (1.0 - r / radius).max(0.0);
+
+        let density = 12.0 * falloff * (1.0 - q.y);
+        let temperature = 2000.0 * falloff.sqrt() * (1.0 - q.y).powi(3);
+        (density, temperature, 0.0)
+    })
+}
+
+/// Generate a scene showing off participating media: smoke, a glass sphere filled with glowing
+/// haze, and a voxel grid of fire
+pub fn volumes(grid: Option<VoxelGrid>) -> Scene {
+    let mut scene: Scene = vec![];
+    scene.push(Arc::new(Sphere {
+        center: Point3::new(0.0, -1000.0, 0.0),
+        radius: 1000.0,
+        material: Arc::new(Lambertian {
+            albedo: Arc::new(Constant {
+                color: Color::new(0.5, 0.5, 0.5, 1.0),
+            }),
+        }),
+    }));
+
+    scene.push(Arc::new(ConstantMedium {
+        boundary: Arc::new(Sphere {
+            center: Point3::new(-0.75, 1.0, 3.2),
+            radius: 1.0,
+            material: Arc::new(Lambertian {
+                albedo: Arc::new(Constant {
+                    color: Color::new(0.0, 0.0, 0.0, 1.0),
+                }),
+            }),
+        }),
+        density: 2.0,
+        material: Arc::new(Volumetric {
+            albedo: Color::new(0.2, 0.2, 0.2, 1.0),
+            phase: Arc::new(Isotropic),
+        }),
+    }));
+
+    // A glass shell with a forward scattering medium inside it gives a subsurface-like glow
+    let center = Point3::new(-0.25, 1.0, 1.05);
+    scene.push(Arc::new(Sphere {
+        center,
+        radius: 1.0,
+        material: Arc::new(Dialectric {
+            albedo: Arc::new(Constant {
+                color: Color::new(1.0, 1.0, 1.0, 1.0),
+            }),
+            ior: Arc::new(Constant::scalar(1.5)),
+            dispersion: None,
+        }),
+    }));
+    scene.push(Arc::new(ConstantMedium {
+        boundary: Arc::new(Sphere {
+            center,
+            radius: 0.99,
+            material: Arc::new(Lambertian {
+                albedo: Arc::new(Constant {
+                    color: Color::new(0.0, 0.0, 0.0, 1.0),
+                }),
+            }),
+        }),
+        density: 4.0,
+        material: Arc::new(Volumetric {
+            albedo: Color::new(0.5, 0.7, 0.95, 1.0),
+            phase: Arc::new(HenyeyGreenstein { g: 0.6 }),
+        }),
+    }));
+
+    let mut grid = grid.unwrap_or_else(plume);
+    grid.place(
+        Point3::new(-0.75, 0.0, -2.05),
+        Point3::new(1.25, 2.5, -0.05),
+    )
+    .expect("volumes scene places the grid within a box");
+    let grid = Arc::new(grid);
+    scene.push(Arc::new(GridMedium {
+        grid: grid.clone(),
+        density_scale: 1.0,
+        material: Arc::new(GridMaterial {
+            grid,
+            albedo: Color::new(0.6, 0.6, 0.6, 1.0),
+            phase: Arc::new(HenyeyGreenstein { g: 0.3 }),
+            emission: Color::new(1.0, 0.5, 0.1, 1.0),
+            temperature_scale: 0.5,
+        }),
+    }));
+
+    scene.push(Arc::new(Sphere {
+        center: Point3::new(0.75, 1.0, -3.2),
+        radius: 1.0,
+        material: Arc::new(Metal {
+            albedo: Arc::new(Constant {
+                color: Color::new(0.7, 0.6, 0.5, 1.0),
+            }),
+            roughness: Arc::new(Constant::scalar(0.0)),
+        }),
+    }));
+
+    scene
+}
+
+/// Generate a scene showing off textured material parameters, optionally mapping an image
+/// around one of the spheres
+pub fn textures(image: Option<ImageTexture>) -> Scene {
+    let mut scene: Scene = vec![];
+    scene.push(Arc::new(Sphere {
+        center: Point3::new(0.0, -1000.0, 0.0),
+        radius: 1000.0,
+        material: Arc::new(Lambertian {
+            albedo: Arc::new(Checker {
+                even: Arc::new(Constant {
+                    color: Color::new(0.2, 0.3, 0.1, 1.0),
+                }),
+                odd: Arc::new(Constant {
+                    color: Color::new(0.9, 0.9, 0.9, 1.0),
+                }),
+                frequency: 10.0,
+            }),
+        }),
+    }));
+
+    let marble: Arc<dyn Texture> = Arc::new(Noise {
+        noise: Arc::new(Perlin::new(0)),
+        pattern: Pattern::Plain,
+        scale: 4.0,
+        low: Color::new(0.0, 0.0, 0.0, 1.0),
+        high: Color::new(0.9, 0.9, 0.85, 1.0),
+    });
+
+    scene.push(Arc::new(Sphere {
+        center: Point3::new(-0.75, 1.0, 3.2),
+        radius: 1.0,
+        material: Arc::new(Lambertian {
+            albedo: match image {
+                Some(image) => Arc::new(image),
+                None => marble.clone(),
+            },
+        }),
+    }));
+
+    scene.push(Arc::new(Sphere {
+        center: Point3::new(-0.25, 1.0, 1.05),
+        radius: 1.0,
+        material: Arc::new(Lambertian { albedo: marble }),
+    }));
+
+    scene.push(Arc::new(Sphere {
+        center: Point3::new(0.25, 1.0, -1.05),
+        radius: 1.0,
+        material: Arc::new(Metal {
+            albedo: Arc::new(Constant {
+                color: Color::new(0.8, 0.6, 0.2, 1.0),
+            }),
+            roughness: Arc::new(Checker {
+                even: Arc::new(Constant::scalar(0.0)),
+                odd: Arc::new(Constant::scalar(0.4)),
+                frequency: 8.0,
+            }),
+        }),
+    }));
+
+    scene.push(Arc::new(Sphere {
+        center: Point3::new(0.75, 1.0, -3.2),
+        radius: 1.0,
+        material: Arc::new(Dialectric {
+            albedo: Arc::new(Checker {
+                even: Arc::new(Constant {
+                    color: Color::new(1.0, 1.0, 1.0, 1.0),
+                }),
+                odd: Arc::new(Constant {
+                    color: Color::new(0.7, 0.85, 1.0, 1.0),
+                }),
+                frequency: 6.0,
+            }),
+            ior: Arc::new(Constant::scalar(1.5)),
+            dispersion: None,
+        }),
+    }));
+
+    scene
+}
+
+/// Generate a scene showing off procedural noise textures: marble, wood, clouds, and cellular
+/// stone, with turbulence driving the roughness of a metal
+pub fn noise(seed: u64) -> Scene {
+    vec![
+        Arc::new(Sphere {
+            center: Point3::new(0.0, -1000.0, 0.0),
+            radius: 1000.0,
+            material: Arc::new(Lambertian {
+                albedo: Arc::new(Noise {
+                    noise: Arc::new(Worley { seed }),
+                    pattern: Pattern::Fbm { octaves: 3 },
+                    scale: 2.0,
+                    low: Color::new(0.15, 0.15, 0.15, 1.0),
+                    high: Color::new(0.7, 0.65, 0.6, 1.0),
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.75, 1.0, 3.2),
+            radius: 1.0,
+            material: Arc::new(Lambertian {
+                albedo: Arc::new(Noise {
+                    noise: Arc::new(Perlin::new(seed)),
+                    pattern: Pattern::Marble { octaves: 7 },
+                    scale: 3.0,
+                    low: Color::new(0.2, 0.2, 0.25, 1.0),
+                    high: Color::new(0.95, 0.95, 0.9, 1.0),
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.25, 1.0, 1.05),
+            radius: 1.0,
+            material: Arc::new(Lambertian {
+                albedo: Arc::new(Noise {
+                    noise: Arc::new(Perlin::new(seed.wrapping_add(1))),
+                    pattern: Pattern::Wood { rings: 8.0 },
+                    scale: 1.0,
+                    low: Color::new(0.3, 0.15, 0.05, 1.0),
+                    high: Color::new(0.65, 0.4, 0.2, 1.0),
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.25, 1.0, -1.05),
+            radius: 1.0,
+            material: Arc::new(Lambertian {
+                albedo: Arc::new(Noise {
+                    noise: Arc::new(Simplex::new(seed)),
+                    pattern: Pattern::Fbm { octaves: 6 },
+                    scale: 2.0,
+                    low: Color::new(0.2, 0.4, 0.9, 1.0),
+                    high: Color::new(1.0, 1.0, 1.0, 1.0),
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.75, 1.0, -3.2),
+            radius: 1.0,
+            material: Arc::new(Metal {
+                albedo: Arc::new(Constant {
+                    color: Color::new(0.8, 0.8, 0.85, 1.0),
+                }),
+                roughness: Arc::new(Noise {
+                    noise: Arc::new(Simplex::new(seed.wrapping_add(2))),
+                    pattern: Pattern::Turbulence { octaves: 4 },
+                    scale: 3.0,
+                    low: Color::new(0.0, 0.0, 0.0, 1.0),
+                    high: Color::new(0.5, 0.5, 0.5, 1.0),
+                }),
+            }),
+        }),
+    ]
+}
+
+/// Generate a tangent space normal map of square tiles with bevelled edges
+fn tiles() -> ImageTexture {
+    let (width, height, tile) = (512, 256, 32);
+    let image = ::image::RgbaImage::from_fn(width, height, |x, y| {
+        let bevel = 0.15;
+        let slope = |i: u32| {
+            let f = (i % tile) as Scalar / tile as Scalar;
+            if f < bevel {
+                -1.0
+            } else if f > 1.0 - bevel {
+                1.0
+            } else {
+                0.0
+            }
+        };
+
+        // Image rows run top to bottom while the bitangent points up the image
+        let n = Vector3::new(slope(x), -slope(y), 1.0).normalize();
+        let encode = |v: Scalar| (255.0 * 0.5 * (v + 1.0)) as u8;
+        ::image::Rgba([encode(n.x), encode(n.y), encode(n.z), 255])
+    });
+
+    ImageTexture { image }
+}
+
+/// Generate a scene showing off normal and bump mapping: tiles, hammered metal, rippled glass
+/// and stucco, optionally using an image as the normal map of the tiles
+pub fn bumps(normal_map: Option<ImageTexture>) -> Scene {
+    vec![
+        Arc::new(Sphere {
+            center: Point3::new(0.0, -1000.0, 0.0),
+            radius: 1000.0,
+            material: Arc::new(BumpMap {
+                height: Arc::new(Noise {
+                    noise: Arc::new(Worley { seed: 0 }),
+                    pattern: Pattern::Plain,
+                    scale: 3.0,
+                    low: Color::new(0.0, 0.0, 0.0, 1.0),
+                    high: Color::new(1.0, 1.0, 1.0, 1.0),
+                }),
+                scale: 0.05,
+                material: Arc::new(Lambertian {
+                    albedo: Arc::new(Constant {
+                        color: Color::new(0.5, 0.5, 0.5, 1.0),
+                    }),
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.75, 1.0, 3.2),
+            radius: 1.0,
+            material: Arc::new(NormalMap {
+                normals: Arc::new(normal_map.unwrap_or_else(tiles)),
+                strength: 1.0,
+                material: Arc::new(Lambertian {
+                    albedo: Arc::new(Constant {
+                        color: Color::new(0.7, 0.3, 0.2, 1.0),
+                    }),
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.25, 1.0, 1.05),
+            radius: 1.0,
+            material: Arc::new(BumpMap {
+                height: Arc::new(Noise {
+                    noise: Arc::new(Worley { seed: 1 }),
+                    pattern: Pattern::Plain,
+                    scale: 6.0,
+                    low: Color::new(0.0, 0.0, 0.0, 1.0),
+                    high: Color::new(1.0, 1.0, 1.0, 1.0),
+                }),
+                scale: 0.02,
+                material: Arc::new(Metal {
+                    albedo: Arc::new(Constant {
+                        color: Color::new(0.8, 0.8, 0.85, 1.0),
+                    }),
+                    roughness: Arc::new(Constant::scalar(0.0)),
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.25, 1.0, -1.05),
+            radius: 1.0,
+            material: Arc::new(BumpMap {
+                height: Arc::new(Noise {
+                    noise: Arc::new(Perlin::new(0)),
+                    pattern: Pattern::Plain,
+                    scale: 4.0,
+                    low: Color::new(0.0, 0.0, 0.0, 1.0),
+                    high: Color::new(1.0, 1.0, 1.0, 1.0),
+                }),
+                scale: 0.05,
+                material: Arc::new(Dialectric {
+                    albedo: Arc::new(Constant {
+                        color: Color::new(1.0, 1.0, 1.0, 1.0),
+                    }),
+                    ior: Arc::new(Constant::scalar(1.5)),
+                    dispersion: None,
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.75, 1.0, -3.2),
+            radius: 1.0,
+            material: Arc::new(BumpMap {
+                height: Arc::new(Noise {
+                    noise: Arc::new(Simplex::new(0)),
+                    pattern: Pattern::Fbm { octaves: 5 },
+                    scale: 12.0,
+                    low: Color::new(0.0, 0.0, 0.0, 1.0),
+                    high: Color::new(1.0, 1.0, 1.0, 1.0),
+                }),
+                scale: 0.01,
+                material: Arc::new(Lambertian {
+                    albedo: Arc::new(Constant {
+                        color: Color::new(0.9, 0.85, 0.7, 1.0),
+                    }),
+                }),
+            }),
+        }),
+    ]
+}
+
+/// Generate a scene showing off rough conductors: polished silver, rough gold, brushed
+/// aluminium, and copper with a roughness varying over its surface
+pub fn metals() -> Scene {
+    vec![
+        Arc::new(Sphere {
+            center: Point3::new(0.0, -1000.0, 0.0),
+            radius: 1000.0,
+            material: Arc::new(Lambertian {
+                albedo: Arc::new(Checker {
+                    even: Arc::new(Constant {
+                        color: Color::new(0.1, 0.1, 0.1, 1.0),
+                    }),
+                    odd: Arc::new(Constant {
+                        color: Color::new(0.8, 0.8, 0.8, 1.0),
+                    }),
+                    frequency: 10.0,
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.75, 1.0, 3.2),
+            radius: 1.0,
+            material: Arc::new(Conductor {
+                ior: SILVER,
+                roughness: Arc::new(Constant::scalar(0.05)),
+                anisotropy: 0.0,
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.25, 1.0, 1.05),
+            radius: 1.0,
+            material: Arc::new(Conductor {
+                ior: GOLD,
+                roughness: Arc::new(Constant::scalar(0.3)),
+                anisotropy: 0.0,
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.25, 1.0, -1.05),
+            radius: 1.0,
+            material: Arc::new(Conductor {
+                ior: ALUMINIUM,
+                roughness: Arc::new(Constant::scalar(0.4)),
+                anisotropy: 0.9,
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.75, 1.0, -3.2),
+            radius: 1.0,
+            material: Arc::new(Conductor {
+                ior: COPPER,
+                roughness: Arc::new(Noise {
+                    noise: Arc::new(Simplex::new(0)),
+                    pattern: Pattern::Fbm { octaves: 4 },
+                    scale: 2.0,
+                    low: Color::new(0.1, 0.1, 0.1, 1.0),
+                    high: Color::new(0.6, 0.6, 0.6, 1.0),
+                }),
+                anisotropy: 0.0,
+            }),
+        }),
+    ]
+}
+
+/// Generate a scene showing off rough glass: lightly and heavily frosted, coloured by
+/// absorption, and frosted in a pattern over its surface
+pub fn glass() -> Scene {
+    vec![
+        Arc::new(Sphere {
+            center: Point3::new(0.0, -1000.0, 0.0),
+            radius: 1000.0,
+            material: Arc::new(Lambertian {
+                albedo: Arc::new(Checker {
+                    even: Arc::new(Constant {
+                        color: Color::new(0.1, 0.1, 0.1, 1.0),
+                    }),
+                    odd: Arc::new(Constant {
+                        color: Color::new(0.8, 0.8, 0.8, 1.0),
+                    }),
+                    frequency: 10.0,
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.75, 1.0, 3.2),
+            radius: 1.0,
+            material: Arc::new(RoughDielectric {
+                ior: Arc::new(Constant::scalar(1.5)),
+                dispersion: None,
+                roughness: Arc::new(Constant::scalar(0.1)),
+                absorption: Color::new(0.0, 0.0, 0.0, 1.0),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.25, 1.0, 1.05),
+            radius: 1.0,
+            material: Arc::new(RoughDielectric {
+                ior: Arc::new(Constant::scalar(1.5)),
+                dispersion: None,
+                roughness: Arc::new(Constant::scalar(0.4)),
+                absorption: Color::new(0.0, 0.0, 0.0, 1.0),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.25, 1.0, -1.05),
+            radius: 1.0,
+            material: Arc::new(RoughDielectric {
+                ior: Arc::new(Constant::scalar(1.5)),
+                dispersion: None,
+                roughness: Arc::new(Constant::scalar(0.05)),
+                absorption: Color::new(1.2, 0.4, 0.1, 1.0),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.75, 1.0, -3.2),
+            radius: 1.0,
+            material: Arc::new(RoughDielectric {
+                ior: Arc::new(Constant::scalar(1.5)),
+                dispersion: None,
+                roughness: Arc::new(Checker {
+                    even: Arc::new(Constant::scalar(0.02)),
+                    odd: Arc::new(Constant::scalar(0.5)),
+                    frequency: 8.0,
+                }),
+                absorption: Color::new(0.1, 0.4, 0.6, 1.0),
+            }),
+        }),
+    ]
+}
+
+/// Generate a scene showing off the principled material: tiles alternating between metal and
+/// plastic, clearcoated car paint, sheened velvet, and tinted transmissive glass
+pub fn principled() -> Scene {
+    vec![
+        Arc::new(Sphere {
+            center: Point3::new(0.0, -1000.0, 0.0),
+            radius: 1000.0,
+            material: Arc::new(Lambertian {
+                albedo: Arc::new(Constant {
+                    color: Color::new(0.5, 0.5, 0.5, 1.0),
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.75, 1.0, 3.2),
+            radius: 1.0,
+            material: Arc::new(Principled {
+                base_color: Arc::new(Constant {
+                    color: Color::new(0.9, 0.6, 0.2, 1.0),
+                }),
+                metallic: Arc::new(Checker {
+                    even: Arc::new(Constant::scalar(0.0)),
+                    odd: Arc::new(Constant::scalar(1.0)),
+                    frequency: 8.0,
+                }),
+                roughness: Arc::new(Constant::scalar(0.3)),
+                specular: 0.5,
+                sheen: 0.0,
+                sheen_tint: 0.0,
+                clearcoat: 0.0,
+                clearcoat_roughness: 0.0,
+                transmission: 0.0,
+                ior: Arc::new(Constant::scalar(1.5)),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.25, 1.0, 1.05),
+            radius: 1.0,
+            material: Arc::new(Principled {
+                base_color: Arc::new(Constant {
+                    color: Color::new(0.05, 0.1, 0.5, 1.0),
+                }),
+                metallic: Arc::new(Constant::scalar(0.3)),
+                roughness: Arc::new(Constant::scalar(0.5)),
+                specular: 0.5,
+                sheen: 0.0,
+                sheen_tint: 0.0,
+                clearcoat: 1.0,
+                clearcoat_roughness: 0.05,
+                transmission: 0.0,
+                ior: Arc::new(Constant::scalar(1.5)),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.25, 1.0, -1.05),
+            radius: 1.0,
+            material: Arc::new(Principled {
+                base_color: Arc::new(Constant {
+                    color: Color::new(0.5, 0.05, 0.1, 1.0),
+                }),
+                metallic: Arc::new(Constant::scalar(0.0)),
+                roughness: Arc::new(Constant::scalar(1.0)),
+                specular: 0.2,
+                sheen: 1.0,
+                sheen_tint: 0.5,
+                clearcoat: 0.0,
+                clearcoat_roughness: 0.0,
+                transmission: 0.0,
+                ior: Arc::new(Constant::scalar(1.5)),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.75, 1.0, -3.2),
+            radius: 1.0,
+            material: Arc::new(Principled {
+                base_color: Arc::new(Constant {
+                    color: Color::new(0.6, 1.0, 0.7, 1.0),
+                }),
+                metallic: Arc::new(Constant::scalar(0.0)),
+                roughness: Arc::new(Constant::scalar(0.1)),
+                specular: 0.5,
+                sheen: 0.0,
+                sheen_tint: 0.0,
+                clearcoat: 0.0,
+                clearcoat_roughness: 0.0,
+                transmission: 1.0,
+                ior: Arc::new(Constant::scalar(1.5)),
+            }),
+        }),
+    ]
+}
+
+/// Generate a scene showing off dispersion when rendered spectrally: crown and flint glass,
+/// glass following an exaggerated Cauchy model, and rough flint glass
+pub fn dispersion() -> Scene {
+    let clear = || -> Arc<dyn Texture> {
+        Arc::new(Constant {
+            color: Color::new(1.0, 1.0, 1.0, 1.0),
+        })
+    };
+
+    vec![
+        Arc::new(Sphere {
+            center: Point3::new(0.0, -1000.0, 0.0),
+            radius: 1000.0,
+            material: Arc::new(Lambertian {
+                albedo: Arc::new(Constant {
+                    color: Color::new(0.8, 0.8, 0.8, 1.0),
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.75, 1.0, 3.2),
+            radius: 1.0,
+            material: Arc::new(Dialectric {
+                albedo: clear(),
+                ior: Arc::new(Constant::scalar(1.5)),
+                dispersion: Some(BK7),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.25, 1.0, 1.05),
+            radius: 1.0,
+            material: Arc::new(Dialectric {
+                albedo: clear(),
+                ior: Arc::new(Constant::scalar(1.78)),
+                dispersion: Some(SF11),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.25, 1.0, -1.05),
+            radius: 1.0,
+            material: Arc::new(Dialectric {
+                albedo: clear(),
+                ior: Arc::new(Constant::scalar(1.6)),
+                dispersion: Some(Dispersion::Cauchy { a: 1.5, b: 0.03 }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.75, 1.0, -3.2),
+            radius: 1.0,
+            material: Arc::new(RoughDielectric {
+                ior: Arc::new(Constant::scalar(1.78)),
+                dispersion: Some(SF11),
+                roughness: Arc::new(Constant::scalar(0.1)),
+                absorption: Color::new(0.0, 0.0, 0.0, 1.0),
+            }),
+        }),
+    ]
+}
+
+/// Generate a scene showing off coatings: a soap bubble, glossy car paint, lacquered wood,
+/// and an iridescent slick of oil over a dark base
+pub fn coatings() -> Scene {
+    let clear_coat = Arc::new(Dialectric {
+        albedo: Arc::new(Constant {
+            color: Color::new(1.0, 1.0, 1.0, 1.0),
+        }),
+        ior: Arc::new(Constant::scalar(1.5)),
+        dispersion: None,
+    });
+
+    vec![
+        Arc::new(Sphere {
+            center: Point3::new(0.0, -1000.0, 0.0),
+            radius: 1000.0,
+            material: Arc::new(Lambertian {
+                albedo: Arc::new(Checker {
+                    even: Arc::new(Constant {
+                        color: Color::new(0.1, 0.1, 0.1, 1.0),
+                    }),
+                    odd: Arc::new(Constant {
+                        color: Color::new(0.8, 0.8, 0.8, 1.0),
+                    }),
+                    frequency: 10.0,
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.75, 1.0, 3.2),
+            radius: 1.0,
+            material: Arc::new(ThinFilm {
+                thickness: Arc::new(Noise {
+                    noise: Arc::new(Perlin::new(0)),
+                    pattern: Pattern::Fbm { octaves: 3 },
+                    scale: 1.5,
+                    low: Color::new(200.0, 200.0, 200.0, 1.0),
+                    high: Color::new(900.0, 900.0, 900.0, 1.0),
+                }),
+                film_ior: 1.33,
+                ior: 1.0,
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.25, 1.0, 1.05),
+            radius: 1.0,
+            material: Arc::new(Layered {
+                coat: clear_coat,
+                base: Arc::new(Lambertian {
+                    albedo: Arc::new(Constant {
+                        color: Color::new(0.6, 0.02, 0.02, 1.0),
+                    }),
+                }),
+                thickness: 0.0,
+                absorption: Color::new(0.0, 0.0, 0.0, 1.0),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.25, 1.0, -1.05),
+            radius: 1.0,
+            material: Arc::new(Layered {
+                coat: Arc::new(RoughDielectric {
+                    ior: Arc::new(Constant::scalar(1.5)),
+                    dispersion: None,
+                    roughness: Arc::new(Constant::scalar(0.15)),
+                    absorption: Color::new(0.0, 0.0, 0.0, 1.0),
+                }),
+                base: Arc::new(Lambertian {
+                    albedo: Arc::new(Noise {
+                        noise: Arc::new(Perlin::new(1)),
+                        pattern: Pattern::Wood { rings: 8.0 },
+                        scale: 1.0,
+                        low: Color::new(0.5, 0.3, 0.15, 1.0),
+                        high: Color::new(0.9, 0.7, 0.5, 1.0),
+                    }),
+                }),
+                thickness: 0.05,
+                absorption: Color::new(0.5, 2.0, 6.0, 1.0),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.75, 1.0, -3.2),
+            radius: 1.0,
+            material: Arc::new(Layered {
+                coat: Arc::new(ThinFilm {
+                    thickness: Arc::new(Noise {
+                        noise: Arc::new(Simplex::new(0)),
+                        pattern: Pattern::Turbulence { octaves: 3 },
+                        scale: 1.0,
+                        low: Color::new(250.0, 250.0, 250.0, 1.0),
+                        high: Color::new(700.0, 700.0, 700.0, 1.0),
+                    }),
+                    film_ior: 1.45,
+                    ior: 1.33,
+                }),
+                base: Arc::new(Lambertian {
+                    albedo: Arc::new(Constant {
+                        color: Color::new(0.02, 0.02, 0.02, 1.0),
+                    }),
+                }),
+                thickness: 0.0,
+                absorption: Color::new(0.0, 0.0, 0.0, 1.0),
+            }),
+        }),
+    ]
+}
+
+/// Generate a scene showing off subsurface scattering: skin, candle wax, marble, and jade
+pub fn subsurface() -> Scene {
+    let material = |albedo: Color, mean_free_path: Color, roughness: Scalar| {
+        Arc::new(Subsurface::new(
+            albedo,
+            mean_free_path,
+            1.4,
+            Arc::new(Constant::scalar(roughness)),
+            0.0,
+        ))
+    };
+
+    vec![
+        Arc::new(Sphere {
+            center: Point3::new(0.0, -1000.0, 0.0),
+            radius: 1000.0,
+            material: Arc::new(Lambertian {
+                albedo: Arc::new(Checker {
+                    even: Arc::new(Constant {
+                        color: Color::new(0.1, 0.1, 0.1, 1.0),
+                    }),
+                    odd: Arc::new(Constant {
+                        color: Color::new(0.8, 0.8, 0.8, 1.0),
+                    }),
+                    frequency: 10.0,
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.75, 1.0, 3.2),
+            radius: 1.0,
+            material: material(
+                Color::new(0.85, 0.55, 0.45, 1.0),
+                Color::new(0.4, 0.15, 0.08, 1.0),
+                0.35,
+            ),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.25, 1.0, 1.05),
+            radius: 1.0,
+            material: material(
+                Color::new(0.9, 0.8, 0.6, 1.0),
+                Color::new(0.5, 0.4, 0.25, 1.0),
+                0.2,
+            ),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.25, 1.0, -1.05),
+            radius: 1.0,
+            material: material(
+                Color::new(0.85, 0.85, 0.82, 1.0),
+                Color::new(0.2, 0.2, 0.18, 1.0),
+                0.05,
+            ),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.75, 1.0, -3.2),
+            radius: 1.0,
+            material: material(
+                Color::new(0.3, 0.7, 0.4, 1.0),
+                Color::new(0.15, 0.35, 0.2, 1.0),
+                0.02,
+            ),
+        }),
+    ]
+}
+
+/// Generate a scene showing off blended materials: rusty metal, a half metallic mix, a two
+/// sided checkerboard shell with holes cut out, and a partially transparent cloud of dots
+pub fn blends() -> Scene {
+    let iron = Arc::new(Conductor {
+        ior: ALUMINIUM,
+        roughness: Arc::new(Constant::scalar(0.25)),
+        anisotropy: 0.0,
+    });
+    let rust = Arc::new(Lambertian {
+        albedo: Arc::new(Noise {
+            noise: Arc::new(Perlin::new(2)),
+            pattern: Pattern::Fbm { octaves: 4 },
+            scale: 8.0,
+            low: Color::new(0.25, 0.08, 0.02, 1.0),
+            high: Color::new(0.5, 0.22, 0.08, 1.0),
+        }),
+    });
+    let holes = Arc::new(Checker {
+        even: Arc::new(Constant::scalar(1.0)),
+        odd: Arc::new(Constant::scalar(0.0)),
+        frequency: 6.0,
+    });
+
+    vec![
+        Arc::new(Sphere {
+            center: Point3::new(0.0, -1000.0, 0.0),
+            radius: 1000.0,
+            material: Arc::new(Lambertian {
+                albedo: Arc::new(Checker {
+                    even: Arc::new(Constant {
+                        color: Color::new(0.1, 0.1, 0.1, 1.0),
+                    }),
+                    odd: Arc::new(Constant {
+                        color: Color::new(0.8, 0.8, 0.8, 1.0),
+                    }),
+                    frequency: 10.0,
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.75, 1.0, 3.2),
+            radius: 1.0,
+            material: Arc::new(Mix {
+                a: iron,
+                b: rust,
+                weight: Arc::new(Noise {
+                    noise: Arc::new(Perlin::new(3)),
+                    pattern: Pattern::Fbm { octaves: 5 },
+                    scale: 2.0,
+                    low: Color::new(-1.5, -1.5, -1.5, 1.0),
+                    high: Color::new(2.5, 2.5, 2.5, 1.0),
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(-0.25, 1.0, 1.05),
+            radius: 1.0,
+            material: Arc::new(Mix {
+                a: Arc::new(Lambertian {
+                    albedo: Arc::new(Constant {
+                        color: Color::new(0.1, 0.2, 0.5, 1.0),
+                    }),
+                }),
+                b: Arc::new(Conductor {
+                    ior: GOLD,
+                    roughness: Arc::new(Constant::scalar(0.2)),
+                    anisotropy: 0.0,
+                }),
+                weight: Arc::new(Constant::scalar(0.5)),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.25, 1.0, -1.05),
+            radius: 1.0,
+            material: Arc::new(AlphaMask {
+                mask: holes,
+                material: Arc::new(TwoSided {
+                    material: Arc::new(Lambertian {
+                        albedo: Arc::new(Constant {
+                            color: Color::new(0.8, 0.4, 0.1, 1.0),
+                        }),
+                    }),
+                }),
+            }),
+        }),
+        Arc::new(Sphere {
+            center: Point3::new(0.75, 1.0, -3.2),
+            radius: 1.0,
+            material: Arc::new(AlphaMask {
+                mask: Arc::new(Noise {
+                    noise: Arc::new(Worley { seed: 4 }),
+                    pattern: Pattern::Plain,
+                    scale: 4.0,
+                    low: Color::new(1.0, 1.0, 1.0, 1.0),
+                    high: Color::new(0.0, 0.0, 0.0, 1.0),
+                }),
+                material: Arc::new(TwoSided {
+                    material: Arc::new(Lambertian {
+                        albedo: Arc::new(Constant {
+                            color: Color::new(0.2, 0.6, 0.3, 1.0),
+                        }),
+                    }),
+                }),
+            }),
+        }),
+    ]
+}
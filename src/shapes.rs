@@ -1,8 +1,10 @@
 use std::cmp::Ordering;
 use std::sync::Arc;
 
+use rand::random;
+
 use crate::materials::Material;
-use crate::types::{Point3, Ray, Scalar, Vector3};
+use crate::types::{basis, Point2, Point3, Ray, Scalar, Vector3};
 
 /// Result of ray intersection with a shape
 #[derive(Debug, Clone)]
@@ -10,13 +12,75 @@ pub struct HitResult {
     pub t: Scalar,
     pub p: Point3,
     pub normal: Vector3,
+    pub uv: Point2,
+    /// Partial derivative of the hit point with respect to u, zero where there is no surface
+    pub tangent: Vector3,
+    /// Partial derivative of the hit point with respect to v, zero where there is no surface
+    pub bitangent: Vector3,
     pub material: Arc<dyn Material>,
 }
 
+impl HitResult {
+    /// Unit tangent and bitangent forming a right handed frame with the normal, falling back on
+    /// an arbitrary frame where the surface has no usable derivatives
+    pub fn frame(&self) -> (Vector3, Vector3) {
+        let n = self.normal;
+        let t = self.tangent - n * n.dot(&self.tangent);
+        let t = if t.magnitude_squared() > 1e-12 {
+            t.normalize()
+        } else {
+            basis(&n).0
+        };
+
+        (t, n.cross(&t))
+    }
+
+    /// Express a world space direction in the frame of the hit, with the normal along z
+    pub fn to_local(&self, v: &Vector3) -> Vector3 {
+        let (t, b) = self.frame();
+        Vector3::new(v.dot(&t), v.dot(&b), v.dot(&self.normal))
+    }
+
+    /// Express a direction in the frame of the hit in world space
+    pub fn to_world(&self, v: &Vector3) -> Vector3 {
+        let (t, b) = self.frame();
+        t * v.x + b * v.y + self.normal * v.z
+    }
+}
+
 /// Shape defines objects intersectable by rays
 pub trait Shape: Send + Sync {
     /// Does an incoming ray intersect this shape
     fn hit(&self, ray: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitResult>;
+
+    /// Fraction of light passing through this shape along a ray between t_min and t_max
+    fn transmittance(&self, ray: &Ray, t_min: Scalar, t_max: Scalar) -> Scalar {
+        let mut transmittance = 1.0;
+        let mut t_min = t_min;
+        while let Some(hit) = self.hit(ray, t_min, t_max) {
+            transmittance *= 1.0 - hit.material.opacity(&hit);
+            if transmittance <= 0.0 {
+                return 0.0;
+            }
+            t_min = hit.t;
+        }
+
+        transmittance
+    }
+}
+
+/// Nearest hit of a ray with a shape, stochastically passing through partially transparent
+/// surfaces in proportion to their opacity
+fn hit_opaque(shape: &dyn Shape, ray: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitResult> {
+    let mut t_min = t_min;
+    loop {
+        let hit = shape.hit(ray, t_min, t_max)?;
+        let opacity = hit.material.opacity(&hit);
+        if opacity >= 1.0 || random::<Scalar>() < opacity {
+            return Some(hit);
+        }
+        t_min = hit.t;
+    }
 }
 
 /// Spherical shape
@@ -39,23 +103,48 @@ impl Shape for Sphere {
         let t1 = (-b - discriminant.sqrt()) / (2.0 * a);
         let t2 = (-b + discriminant.sqrt()) / (2.0 * a);
 
-        let t = if discriminant > 0.0 && t1 < t2 && t1 > t_min && t1 < t_max {
+        let t = if discriminant > 0.0 && t1 > t_min && t1 < t_max {
             Some(t1)
-        } else if discriminant > 0.0 && t2 < t1 && t2 > t_min && t2 < t_max {
+        } else if discriminant > 0.0 && t2 > t_min && t2 < t_max {
             Some(t2)
         } else {
             None
         };
 
-        t.and_then(|t| {
+        t.map(|t| {
             let p = ray.at(t);
             let normal = (p - self.center) / self.radius;
-            Some(HitResult {
+
+            // Longitude and latitude of the hit, measured from -x and -y respectively
+            let phi = (-normal.z).atan2(normal.x) + std::f32::consts::PI;
+            let theta = (-normal.y).clamp(-1.0, 1.0).acos();
+            let uv = Point2::new(
+                phi / (2.0 * std::f32::consts::PI),
+                theta / std::f32::consts::PI,
+            );
+
+            // Derivatives of the point with respect to u around the y axis and v toward +y, both
+            // of which vanish or are undefined at the poles
+            let rho = (normal.x * normal.x + normal.z * normal.z).sqrt();
+            let tangent =
+                2.0 * std::f32::consts::PI * self.radius * Vector3::new(normal.z, 0.0, -normal.x);
+            let bitangent = if rho > 0.0 {
+                std::f32::consts::PI
+                    * self.radius
+                    * Vector3::new(-normal.y * normal.x / rho, rho, -normal.y * normal.z / rho)
+            } else {
+                Vector3::zeros()
+            };
+
+            HitResult {
                 t,
                 p,
                 normal,
+                uv,
+                tangent,
+                bitangent,
                 material: self.material.clone(),
-            })
+            }
         })
     }
 }
@@ -68,7 +157,20 @@ impl Shape for Scene {
     fn hit(&self, ray: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitResult> {
         // TODO: Use an acceleration structure such as a BVH to optimize this
         self.iter()
-            .filter_map(|h| h.hit(&ray, t_min, t_max))
+            .filter_map(|h| hit_opaque(h.as_ref(), ray, t_min, t_max))
             .min_by(|x, y| x.t.partial_cmp(&y.t).unwrap_or(Ordering::Equal))
     }
+
+    /// Fraction of light passing through this shape along a ray between t_min and t_max
+    fn transmittance(&self, ray: &Ray, t_min: Scalar, t_max: Scalar) -> Scalar {
+        let mut transmittance = 1.0;
+        for shape in self.iter() {
+            transmittance *= shape.transmittance(ray, t_min, t_max);
+            if transmittance <= 0.0 {
+                break;
+            }
+        }
+
+        transmittance
+    }
 }
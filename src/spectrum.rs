@@ -0,0 +1,230 @@
+use rand::random;
+
+use crate::types::{Color, Scalar};
+
+/// Shortest wavelength in nanometers sampled when rendering spectrally
+pub const LAMBDA_MIN: Scalar = 380.0;
+
+/// Longest wavelength in nanometers sampled when rendering spectrally
+pub const LAMBDA_MAX: Scalar = 780.0;
+
+/// Piecewise gaussian used by the analytic fit to the CIE color matching functions
+fn gaussian(x: Scalar, mu: Scalar, sigma_lo: Scalar, sigma_hi: Scalar) -> Scalar {
+    let t = (x - mu) / if x < mu { sigma_lo } else { sigma_hi };
+    (-0.5 * t * t).exp()
+}
+
+/// CIE 1931 XYZ color matching functions at a wavelength in nanometers
+///
+/// Uses the multi-lobe gaussian fit from Wyman, Sloan and Shirley, "Simple Analytic
+/// Approximations to the CIE XYZ Color Matching Functions" (2013).
+pub fn cie_xyz(lambda: Scalar) -> (Scalar, Scalar, Scalar) {
+    let x = 1.056 * gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * gaussian(lambda, 501.1, 20.4, 26.2);
+    let y =
+        0.821 * gaussian(lambda, 568.8, 46.9, 40.5) + 0.286 * gaussian(lambda, 530.9, 16.3, 31.1);
+    let z =
+        1.217 * gaussian(lambda, 437.0, 11.8, 36.0) + 0.681 * gaussian(lambda, 459.0, 26.0, 13.8);
+    (x, y, z)
+}
+
+/// Convert CIE XYZ tristimulus values to linear sRGB
+pub fn xyz_to_rgb(x: Scalar, y: Scalar, z: Scalar) -> Color {
+    Color::new(
+        3.240_454 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+        -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z,
+        0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+        1.0,
+    )
+}
+
+/// Spectral radiance of a blackbody at a wavelength in nanometers and temperature in kelvin
+fn planck(lambda: Scalar, temperature: Scalar) -> Scalar {
+    const C2: Scalar = 1.438_777e7; // Second radiation constant in nm * K
+    let l = lambda / 1000.0;
+    1.0 / (l.powi(5) * ((C2 / (lambda * temperature)).exp() - 1.0))
+}
+
+/// Color of a blackbody radiator at the given temperature in kelvin, normalized to unit luminance
+pub fn blackbody(temperature: Scalar) -> Color {
+    if temperature <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0, 1.0);
+    }
+
+    let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+    let mut lambda = 380.0;
+    while lambda <= 780.0 {
+        let power = planck(lambda, temperature);
+        let (cx, cy, cz) = cie_xyz(lambda);
+        x += power * cx;
+        y += power * cy;
+        z += power * cz;
+        lambda += 10.0;
+    }
+
+    let rgb = xyz_to_rgb(x / y, 1.0, z / y);
+    Color::new(rgb.r.max(0.0), rgb.g.max(0.0), rgb.b.max(0.0), 1.0)
+}
+
+/// Value at a wavelength in nanometers of a smooth spectrum approximating an RGB color
+///
+/// Blends the channels with logistic curves which sum to one everywhere, so that grays map to
+/// flat spectra and the conversion is linear in the color.
+pub fn rgb_to_spectrum(c: &Color, lambda: Scalar) -> Scalar {
+    let blue = 1.0 / (1.0 + ((lambda - 490.0) / 15.0).exp());
+    let red = 1.0 / (1.0 + ((590.0 - lambda) / 15.0).exp());
+    let green = (1.0 - blue - red).max(0.0);
+    c.r * red + c.g * green + c.b * blue
+}
+
+/// Film converting radiance carried at single sampled wavelengths back into RGB
+///
+/// Paths still carry RGB attenuation from bounce to bounce, with the wavelength of the path
+/// only changing the index of refraction of dispersive glass, so this gives dispersion rather
+/// than full spectral transport. Radiance is projected onto the wavelength only at the film.
+#[derive(Debug, Clone)]
+pub struct SpectralFilm {
+    /// Color of a flat spectrum, divided out to keep grays neutral
+    white: Color,
+}
+
+impl Default for SpectralFilm {
+    fn default() -> Self {
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        let mut lambda = LAMBDA_MIN;
+        while lambda <= LAMBDA_MAX {
+            let (cx, cy, cz) = cie_xyz(lambda);
+            x += cx;
+            y += cy;
+            z += cz;
+            lambda += 1.0;
+        }
+
+        Self {
+            white: xyz_to_rgb(x, y, z),
+        }
+    }
+}
+
+impl SpectralFilm {
+    /// Choose a wavelength in nanometers for one of a number of paths through a pixel,
+    /// stratifying the paths over the spectrum to reduce color noise
+    pub fn sample_wavelength(&self, index: u32, count: u32) -> Scalar {
+        let u = (index as Scalar + random::<Scalar>()) / count as Scalar;
+        LAMBDA_MIN + u * (LAMBDA_MAX - LAMBDA_MIN)
+    }
+
+    /// Estimate the RGB contribution of a path which carried the given radiance at a sampled
+    /// wavelength, projecting the radiance onto that wavelength and dividing by the density of
+    /// choosing it. Wavelengths lying outside the RGB gamut give negative components, which only
+    /// cancel out once averaged over the pixel, where the framebuffer clamps them.
+    pub fn to_rgb(&self, radiance: &Color, lambda: Scalar) -> Color {
+        let value = rgb_to_spectrum(radiance, lambda) * (LAMBDA_MAX - LAMBDA_MIN);
+        let (x, y, z) = cie_xyz(lambda);
+        let rgb = xyz_to_rgb(x, y, z);
+        Color::new(
+            value * rgb.r / self.white.r,
+            value * rgb.g / self.white.g,
+            value * rgb.b / self.white.b,
+            radiance.a,
+        )
+    }
+}
+
+/// Model of the variation of the index of refraction of a dielectric with wavelength
+#[derive(Debug, Clone, Copy)]
+pub enum Dispersion {
+    /// Cauchy's equation, n = a + b / l^2 with l in micrometers
+    Cauchy { a: Scalar, b: Scalar },
+    /// Sellmeier equation, n^2 = 1 + sum of b l^2 / (l^2 - c) with l in micrometers
+    Sellmeier { b: [Scalar; 3], c: [Scalar; 3] },
+}
+
+impl Dispersion {
+    /// Index of refraction at a wavelength in nanometers
+    pub fn ior(&self, lambda: Scalar) -> Scalar {
+        let l2 = (lambda / 1000.0).powi(2);
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => (1.0
+                + b.iter()
+                    .zip(c.iter())
+                    .map(|(b, c)| b * l2 / (l2 - c))
+                    .sum::<Scalar>())
+            .sqrt(),
+        }
+    }
+}
+
+/// Sellmeier coefficients of Schott N-BK7, a common borosilicate crown glass
+pub const BK7: Dispersion = Dispersion::Sellmeier {
+    b: [1.039_612, 0.231_792_34, 1.010_469_5],
+    c: [0.006_000_699, 0.020_017_914, 103.560_65],
+};
+
+/// Sellmeier coefficients of Schott SF11, a strongly dispersive dense flint glass
+pub const SF11: Dispersion = Dispersion::Sellmeier {
+    b: [1.737_597, 0.313_747_35, 1.898_781],
+    c: [0.013_188_707, 0.062_306_81, 155.236_3],
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wavelengths in nanometers of the helium d, hydrogen F and hydrogen C Fraunhofer lines
+    const D: Scalar = 587.56;
+    const F: Scalar = 486.13;
+    const C: Scalar = 656.27;
+
+    /// Abbe number measuring how little a glass disperses visible light
+    fn abbe(dispersion: &Dispersion) -> Scalar {
+        (dispersion.ior(D) - 1.0) / (dispersion.ior(F) - dispersion.ior(C))
+    }
+
+    #[test]
+    fn sellmeier_glasses_match_their_catalog_values() {
+        assert!((BK7.ior(D) - 1.5168).abs() < 1e-4, "{}", BK7.ior(D));
+        assert!((SF11.ior(D) - 1.7847).abs() < 1e-4, "{}", SF11.ior(D));
+        assert!((abbe(&BK7) - 64.17).abs() < 0.1, "{}", abbe(&BK7));
+        assert!((abbe(&SF11) - 25.68).abs() < 0.1, "{}", abbe(&SF11));
+    }
+
+    #[test]
+    fn cauchy_index_follows_its_equation() {
+        let glass = Dispersion::Cauchy { a: 1.5, b: 0.03 };
+        assert!((glass.ior(500.0) - 1.62).abs() < 1e-5);
+        assert!((glass.ior(1000.0) - 1.53).abs() < 1e-5);
+    }
+
+    #[test]
+    fn index_falls_with_wavelength_across_the_visible_range() {
+        for glass in &[BK7, SF11, Dispersion::Cauchy { a: 1.5, b: 0.03 }] {
+            let mut lambda = LAMBDA_MIN;
+            while lambda < LAMBDA_MAX {
+                assert!(glass.ior(lambda) > glass.ior(lambda + 10.0));
+                lambda += 10.0;
+            }
+        }
+    }
+
+    #[test]
+    fn film_keeps_white_white() {
+        let film = SpectralFilm::default();
+        let white = Color::new(1.0, 1.0, 1.0, 1.0);
+        let count = 400;
+        let (mut r, mut g, mut b) = (0.0, 0.0, 0.0);
+        for i in 0..count {
+            let lambda =
+                LAMBDA_MIN + (i as Scalar + 0.5) / count as Scalar * (LAMBDA_MAX - LAMBDA_MIN);
+            let c = film.to_rgb(&white, lambda);
+            r += c.r / count as Scalar;
+            g += c.g / count as Scalar;
+            b += c.b / count as Scalar;
+        }
+        for c in &[r, g, b] {
+            assert!((c - 1.0).abs() < 1e-2, "{} {} {}", r, g, b);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use crate::materials::{Material, ScatteredRay};
+use crate::media::{HenyeyGreenstein, Interior, Isotropic, PhaseFunction};
+use crate::microfacet::RoughDielectric;
+use crate::shapes::HitResult;
+use crate::textures::{Constant, Texture};
+use crate::types::{Color, Ray, Scalar, Vector3};
+
+/// Single scattering albedo giving approximately the requested multiple scattering albedo,
+/// that is the apparent color of a thick slab, using the fit from Chiang, Kutz and Burley,
+/// "Practical and Controllable Subsurface Scattering for Production Path Tracing" (2016)
+fn single_scattering_albedo(albedo: Scalar) -> Scalar {
+    let a = albedo.clamp(0.0, 0.999);
+    let s = 4.097_12 + 4.208_63 * a - (9.592_17 + 41.680_8 * a + 17.712_6 * a * a).sqrt();
+    (1.0 - s * s).clamp(0.0, 1.0)
+}
+
+/// Translucent material such as skin, wax, or marble, through whose rough dielectric surface
+/// light enters and scatters in a random walk before leaving at some other point. Closed
+/// shapes are required so that the walk finds its way back to the surface.
+#[derive(Debug, Clone)]
+pub struct Subsurface {
+    /// Apparent color of the material, which the medium within is fit to
+    albedo: Color,
+    surface: RoughDielectric,
+    interior: Interior,
+}
+
+impl Subsurface {
+    /// Create a material with the given apparent color and average distance travelled by light
+    /// between scattering events in each channel, a surface of the given index of refraction
+    /// and roughness, and Henyey-Greenstein asymmetry of scattering inside
+    pub fn new(
+        albedo: Color,
+        mean_free_path: Color,
+        ior: Scalar,
+        roughness: Arc<dyn Texture>,
+        anisotropy: Scalar,
+    ) -> Self {
+        let phase: Arc<dyn PhaseFunction> = if anisotropy == 0.0 {
+            Arc::new(Isotropic)
+        } else {
+            Arc::new(HenyeyGreenstein { g: anisotropy })
+        };
+        let extinction = |mfp: Scalar| if mfp > 0.0 { 1.0 / mfp } else { 0.0 };
+
+        Self {
+            albedo,
+            surface: RoughDielectric {
+                ior: Arc::new(Constant::scalar(ior)),
+                dispersion: None,
+                roughness,
+                absorption: Color::new(0.0, 0.0, 0.0, 1.0),
+            },
+            interior: Interior {
+                extinction: Color::new(
+                    extinction(mean_free_path.r),
+                    extinction(mean_free_path.g),
+                    extinction(mean_free_path.b),
+                    1.0,
+                ),
+                albedo: Color::new(
+                    single_scattering_albedo(albedo.r),
+                    single_scattering_albedo(albedo.g),
+                    single_scattering_albedo(albedo.b),
+                    1.0,
+                ),
+                phase,
+            },
+        }
+    }
+}
+
+impl Material for Subsurface {
+    /// Calculate scattered ray generated by an incoming ray interacting with this surface
+    fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
+        self.surface.scatter(ray, hit)
+    }
+
+    /// Evaluate the fraction of light arriving from a direction which is scattered along the
+    /// incoming ray, including the cosine term
+    fn eval(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Color> {
+        self.surface.eval(ray, hit, direction)
+    }
+
+    /// Probability density with which scatter() would choose the given direction
+    fn pdf(&self, ray: &Ray, hit: &HitResult, direction: &Vector3) -> Option<Scalar> {
+        self.surface.pdf(ray, hit, direction)
+    }
+
+    /// Color of the surface apart from lighting, guiding the denoiser
+    fn albedo(&self, hit: &HitResult) -> Color {
+        let _ = hit;
+        self.albedo
+    }
+
+    /// Medium filling the inside of closed shapes made of this material
+    fn interior(&self) -> Option<&Interior> {
+        Some(&self.interior)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Point2, Point3};
+
+    #[test]
+    fn albedo_is_the_apparent_color() {
+        let color = Color::new(0.8, 0.5, 0.4, 1.0);
+        let material = Arc::new(Subsurface::new(
+            color,
+            Color::new(0.1, 0.1, 0.1, 1.0),
+            1.4,
+            Arc::new(Constant::scalar(0.3)),
+            0.0,
+        ));
+        let hit = HitResult {
+            t: 1.0,
+            p: Point3::origin(),
+            normal: Vector3::y(),
+            uv: Point2::new(0.5, 0.5),
+            tangent: Vector3::x(),
+            bitangent: -Vector3::z(),
+            material: material.clone(),
+        };
+        assert_eq!(material.albedo(&hit), color);
+    }
+}
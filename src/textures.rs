@@ -0,0 +1,156 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use failure::Error;
+use image::RgbaImage;
+
+use crate::noise::{fbm, turbulence, NoiseFunction};
+use crate::types::{Color, Point2, Point3, Scalar};
+
+/// Texture defines a material parameter varying over a surface
+pub trait Texture: Send + Sync + std::fmt::Debug {
+    /// Evaluate the texture at the given surface coordinates and point in space
+    fn value(&self, uv: &Point2, p: &Point3) -> Color;
+
+    /// Evaluate the texture as a single scalar, for parameters such as roughness
+    fn scalar(&self, uv: &Point2, p: &Point3) -> Scalar {
+        let c = self.value(uv, p);
+        (c.r + c.g + c.b) / 3.0
+    }
+}
+
+/// Texture with the same value everywhere
+#[derive(Debug, Clone)]
+pub struct Constant {
+    pub color: Color,
+}
+
+impl Constant {
+    /// Create a constant texture holding a single scalar value in every channel
+    pub fn scalar(value: Scalar) -> Self {
+        Self {
+            color: Color::new(value, value, value, 1.0),
+        }
+    }
+}
+
+impl Texture for Constant {
+    /// Evaluate the texture at the given surface coordinates and point in space
+    fn value(&self, uv: &Point2, p: &Point3) -> Color {
+        let _ = (uv, p);
+        self.color
+    }
+}
+
+/// Three dimensional checkerboard alternating between two other textures
+#[derive(Debug, Clone)]
+pub struct Checker {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub frequency: Scalar,
+}
+
+impl Texture for Checker {
+    /// Evaluate the texture at the given surface coordinates and point in space
+    fn value(&self, uv: &Point2, p: &Point3) -> Color {
+        let f = self.frequency;
+        let sines = (f * p.x).sin() * (f * p.y).sin() * (f * p.z).sin();
+        if sines < 0.0 {
+            self.odd.value(uv, p)
+        } else {
+            self.even.value(uv, p)
+        }
+    }
+}
+
+/// Texture mapping an image file over surface coordinates
+#[derive(Debug, Clone)]
+pub struct ImageTexture {
+    pub image: RgbaImage,
+}
+
+impl ImageTexture {
+    /// Load an image texture from a file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(Self {
+            image: image::open(path)?.to_rgba(),
+        })
+    }
+}
+
+impl Texture for ImageTexture {
+    /// Evaluate the texture at the given surface coordinates and point in space
+    fn value(&self, uv: &Point2, p: &Point3) -> Color {
+        let _ = p;
+        let (width, height) = self.image.dimensions();
+        if width == 0 || height == 0 {
+            return Color::new(0.0, 1.0, 1.0, 1.0);
+        }
+
+        // Wrap coordinates, with v running from the bottom of the image to the top
+        let u = uv.x - uv.x.floor();
+        let v = 1.0 - (uv.y - uv.y.floor());
+        let x = ((u * width as Scalar) as u32).min(width - 1);
+        let y = ((v * height as Scalar) as u32).min(height - 1);
+
+        (*self.image.get_pixel(x, y)).into()
+    }
+}
+
+/// Pattern formed from a noise function by a procedural texture
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// Noise used as is
+    Plain,
+    /// Fractal Brownian motion over a number of octaves, e.g. for clouds
+    Fbm { octaves: u32 },
+    /// Turbulence over a number of octaves, e.g. for fire or billowing smoke
+    Turbulence { octaves: u32 },
+    /// Sinusoidal veins perturbed by turbulence, as in marble
+    Marble { octaves: u32 },
+    /// Concentric rings around the y axis perturbed by noise, as in wood
+    Wood { rings: Scalar },
+}
+
+/// Procedural texture blending between two colors according to a pattern of noise
+#[derive(Debug, Clone)]
+pub struct Noise {
+    pub noise: Arc<dyn NoiseFunction>,
+    pub pattern: Pattern,
+    pub scale: Scalar,
+    pub low: Color,
+    pub high: Color,
+}
+
+impl Noise {
+    /// Evaluate the pattern at a point, returning a value between 0.0 and 1.0
+    fn pattern(&self, p: &Point3) -> Scalar {
+        let q = p * self.scale;
+        let noise = self.noise.as_ref();
+
+        let value = match self.pattern {
+            Pattern::Plain => 0.5 * (1.0 + noise.noise(&q)),
+            Pattern::Fbm { octaves } => 0.5 * (1.0 + fbm(noise, &q, octaves)),
+            Pattern::Turbulence { octaves } => turbulence(noise, &q, octaves),
+            Pattern::Marble { octaves } => {
+                0.5 * (1.0 + (q.z + 10.0 * turbulence(noise, &q, octaves)).sin())
+            }
+            Pattern::Wood { rings } => {
+                let r = (q.x * q.x + q.z * q.z).sqrt() + 0.2 * noise.noise(&q);
+                let grain = r * rings;
+                grain - grain.floor()
+            }
+        };
+
+        value.clamp(0.0, 1.0)
+    }
+}
+
+impl Texture for Noise {
+    /// Evaluate the texture at the given surface coordinates and point in space
+    fn value(&self, uv: &Point2, p: &Point3) -> Color {
+        let _ = uv;
+        let t = self.pattern(p);
+        self.low * (1.0 - t) + self.high * t
+    }
+}
@@ -2,6 +2,7 @@ use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
 
 pub type Scalar = f32;
 pub type Vector3 = bvh::nalgebra::Vector3<Scalar>;
+pub type Point2 = bvh::nalgebra::Point2<Scalar>;
 pub type Point3 = bvh::nalgebra::Point3<Scalar>;
 
 /// A ray consisting of an origin point and direction vector
@@ -9,6 +10,8 @@ pub type Point3 = bvh::nalgebra::Point3<Scalar>;
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vector3,
+    /// Wavelength in nanometers carried by the ray when rendering spectrally
+    pub wavelength: Option<Scalar>,
 }
 
 impl Ray {
@@ -17,6 +20,7 @@ impl Ray {
         Self {
             origin,
             direction: direction.normalize(),
+            wavelength: None,
         }
     }
 
@@ -32,12 +36,24 @@ impl From<bvh::ray::Ray> for Ray {
     }
 }
 
-impl Into<bvh::ray::Ray> for Ray {
-    fn into(self) -> bvh::ray::Ray {
-        bvh::ray::Ray::new(self.origin, self.direction)
+impl From<Ray> for bvh::ray::Ray {
+    fn from(ray: Ray) -> Self {
+        Self::new(ray.origin, ray.direction)
     }
 }
 
+/// Build an orthonormal basis around a given unit vector
+pub fn basis(w: &Vector3) -> (Vector3, Vector3) {
+    let a = if w.x.abs() > 0.9 {
+        Vector3::y()
+    } else {
+        Vector3::x()
+    };
+    let v = w.cross(&a).normalize();
+    let u = w.cross(&v);
+    (u, v)
+}
+
 /// Convert a scalar (float) value between 0.0 and 1.0 to an unsigned byte value between 0 and 255
 fn scalar_to_u8(f: Scalar) -> u8 {
     (f * 255.99) as u8
@@ -62,6 +78,11 @@ impl Color {
     pub fn new(r: Scalar, g: Scalar, b: Scalar, a: Scalar) -> Self {
         Self { r, g, b, a }
     }
+
+    /// Relative luminance of the color
+    pub fn luminance(&self) -> Scalar {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
 }
 
 impl From<image::Rgba<u8>> for Color {
@@ -75,13 +96,13 @@ impl From<image::Rgba<u8>> for Color {
     }
 }
 
-impl Into<image::Rgba<u8>> for Color {
-    fn into(self) -> image::Rgba<u8> {
+impl From<Color> for image::Rgba<u8> {
+    fn from(c: Color) -> Self {
         image::Rgba([
-            scalar_to_u8(self.r),
-            scalar_to_u8(self.g),
-            scalar_to_u8(self.b),
-            scalar_to_u8(self.a),
+            scalar_to_u8(c.r),
+            scalar_to_u8(c.g),
+            scalar_to_u8(c.b),
+            scalar_to_u8(c.a),
         ])
     }
 }
@@ -97,13 +118,9 @@ impl From<image::Rgb<u8>> for Color {
     }
 }
 
-impl Into<image::Rgb<u8>> for Color {
-    fn into(self) -> image::Rgb<u8> {
-        image::Rgb([
-            scalar_to_u8(self.r),
-            scalar_to_u8(self.g),
-            scalar_to_u8(self.b),
-        ])
+impl From<Color> for image::Rgb<u8> {
+    fn from(c: Color) -> Self {
+        image::Rgb([scalar_to_u8(c.r), scalar_to_u8(c.g), scalar_to_u8(c.b)])
     }
 }
 
@@ -118,7 +135,9 @@ impl From<Vector3> for Color {
     }
 }
 
-// Generate code for common aritmatic operations on Colors
+// Generate code for common aritmatic operations on Colors. These act on the red, green, and blue
+// components only, with the alpha channel describing coverage carried over from the left hand
+// side, since attenuating or summing light says nothing about how much of a pixel it covers.
 macro_rules! arith {
     ( $trait:ty, $scalar_trait:ty, $func:ident, $assign_trait:ty, $assign_scalar_trait:ty, $assign_func:ident, $op:tt ) => {
         impl $trait for Color {
@@ -129,7 +148,7 @@ macro_rules! arith {
                     r: self.r $op other.r,
                     g: self.g $op other.g,
                     b: self.b $op other.b,
-                    a: self.a $op other.a,
+                    a: self.a,
                 }
             }
         }
@@ -142,7 +161,7 @@ macro_rules! arith {
                     r: self.r $op other,
                     g: self.g $op other,
                     b: self.b $op other,
-                    a: self.a $op other,
+                    a: self.a,
                 }
             }
         }
@@ -153,7 +172,7 @@ macro_rules! arith {
                     r: self.r $op other.r,
                     g: self.g $op other.g,
                     b: self.b $op other.b,
-                    a: self.a $op other.a,
+                    a: self.a,
                 }
             }
         }
@@ -164,7 +183,7 @@ macro_rules! arith {
                     r: self.r $op other,
                     g: self.g $op other,
                     b: self.b $op other,
-                    a: self.a $op other,
+                    a: self.a,
                 }
             }
         }
@@ -0,0 +1,452 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek};
+use std::path::Path;
+use std::sync::Arc;
+
+use failure::{bail, Error};
+use rand::random;
+
+use crate::materials::{Material, ScatterKind, ScatteredRay};
+use crate::media::PhaseFunction;
+use crate::shapes::{HitResult, Shape};
+use crate::spectrum::blackbody;
+use crate::types::{Color, Point2, Point3, Ray, Scalar, Vector3};
+
+/// Magic line identifying a voxel grid file
+const MAGIC: &str = "RTXVOL";
+
+/// Names of the channels a voxel grid file may hold
+const CHANNELS: [&str; 3] = ["density", "temperature", "emission"];
+
+/// Dense grid of voxels spanning an axis aligned box, with density and optional temperature
+/// and emission channels. Grids initially span the unit cube, and may be placed anywhere in a
+/// scene by adjusting their bounds.
+///
+/// Grid files consist of three text lines: the magic string `RTXVOL`, the resolution along
+/// each axis (e.g. `64 64 64`), and the names of the channels stored per voxel (`density`
+/// followed by any of `temperature` or `emission`). Voxel values follow as little endian 32-bit
+/// floats, interleaved by channel with the x index varying fastest.
+#[derive(Debug, Clone)]
+pub struct VoxelGrid {
+    min: Point3,
+    max: Point3,
+    resolution: [usize; 3],
+    density: Vec<Scalar>,
+    temperature: Option<Vec<Scalar>>,
+    emission: Option<Vec<Scalar>>,
+    max_density: Scalar,
+}
+
+impl VoxelGrid {
+    /// Create a grid by evaluating a function returning density, temperature and emission at
+    /// each voxel, given in coordinates between 0.0 and 1.0 along each axis
+    pub fn from_fn<F>(resolution: [usize; 3], f: F) -> Self
+    where
+        F: Fn(Point3) -> (Scalar, Scalar, Scalar),
+    {
+        let count = resolution[0] * resolution[1] * resolution[2];
+        let mut density = Vec::with_capacity(count);
+        let mut temperature = Vec::with_capacity(count);
+        let mut emission = Vec::with_capacity(count);
+
+        for z in 0..resolution[2] {
+            for y in 0..resolution[1] {
+                for x in 0..resolution[0] {
+                    let (d, t, e) = f(Point3::new(
+                        (x as Scalar + 0.5) / resolution[0] as Scalar,
+                        (y as Scalar + 0.5) / resolution[1] as Scalar,
+                        (z as Scalar + 0.5) / resolution[2] as Scalar,
+                    ));
+                    density.push(d);
+                    temperature.push(t);
+                    emission.push(e);
+                }
+            }
+        }
+
+        Self::from_channels(resolution, density, Some(temperature), Some(emission))
+    }
+
+    /// Load a grid from a file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+
+        reader.read_line(&mut line)?;
+        if line.trim() != MAGIC {
+            bail!("not a voxel grid file");
+        }
+
+        line.clear();
+        reader.read_line(&mut line)?;
+        let dims = line
+            .split_whitespace()
+            .map(|s| s.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()?;
+        if dims.len() != 3 || dims.contains(&0) {
+            bail!("invalid voxel grid resolution: {}", line.trim());
+        }
+        let resolution = [dims[0], dims[1], dims[2]];
+
+        line.clear();
+        reader.read_line(&mut line)?;
+        let names: Vec<String> = line.split_whitespace().map(String::from).collect();
+        if names.first().map(String::as_str) != Some("density") {
+            bail!("voxel grid must begin with a density channel");
+        }
+        for (i, name) in names.iter().enumerate() {
+            if !CHANNELS.contains(&name.as_str()) {
+                bail!("unknown voxel grid channel: {}", name);
+            }
+            if names[..i].contains(name) {
+                bail!("duplicate voxel grid channel: {}", name);
+            }
+        }
+
+        // Check the size of the voxel data against the file before allocating room for it
+        let count = resolution[0]
+            .checked_mul(resolution[1])
+            .and_then(|n| n.checked_mul(resolution[2]));
+        let size = count
+            .and_then(|n| n.checked_mul(names.len()))
+            .and_then(|n| n.checked_mul(4));
+        let (count, size) = match (count, size) {
+            (Some(count), Some(size)) => (count, size),
+            _ => bail!("voxel grid resolution is too large: {}", line.trim()),
+        };
+        if size as u64 > length.saturating_sub(reader.stream_position()?) {
+            bail!("voxel grid file is shorter than its resolution requires");
+        }
+
+        let mut bytes = vec![0u8; size];
+        reader.read_exact(&mut bytes)?;
+
+        let mut channels = vec![Vec::with_capacity(count); names.len()];
+        for (i, chunk) in bytes.chunks_exact(4).enumerate() {
+            let value = Scalar::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            // Tracking through the grid relies on densities lying between zero and the maximum
+            if !value.is_finite() || value < 0.0 {
+                bail!(
+                    "invalid {} in voxel grid: {}",
+                    names[i % names.len()],
+                    value
+                );
+            }
+            channels[i % names.len()].push(value);
+        }
+
+        let mut channel = |name: &str| {
+            names
+                .iter()
+                .position(|n| n == name)
+                .map(|i| std::mem::take(&mut channels[i]))
+        };
+
+        let density = channel("density").unwrap_or_default();
+        let temperature = channel("temperature");
+        let emission = channel("emission");
+
+        Ok(Self::from_channels(
+            resolution,
+            density,
+            temperature,
+            emission,
+        ))
+    }
+
+    /// Create a grid from already populated channels
+    fn from_channels(
+        resolution: [usize; 3],
+        density: Vec<Scalar>,
+        temperature: Option<Vec<Scalar>>,
+        emission: Option<Vec<Scalar>>,
+    ) -> Self {
+        let max_density = density.iter().cloned().fold(0.0, Scalar::max);
+        Self {
+            min: Point3::origin(),
+            max: Point3::new(1.0, 1.0, 1.0),
+            resolution,
+            density,
+            temperature,
+            emission,
+            max_density,
+        }
+    }
+
+    /// Place the grid to span an axis aligned box, which must have some extent along each axis
+    pub fn place(&mut self, min: Point3, max: Point3) -> Result<(), Error> {
+        if (0..3).any(|axis| min[axis] >= max[axis]) {
+            bail!("voxel grid bounds are empty: {:?} to {:?}", min, max);
+        }
+        self.min = min;
+        self.max = max;
+        Ok(())
+    }
+
+    /// Trilinearly interpolate a channel at a point in world space
+    fn lookup(&self, channel: &[Scalar], p: &Point3) -> Scalar {
+        let extent = self.max - self.min;
+        let local = p - self.min;
+        let mut index = [0usize; 3];
+        let mut frac = [0.0; 3];
+
+        for axis in 0..3 {
+            let n = self.resolution[axis];
+            let g = (local[axis] / extent[axis] * n as Scalar - 0.5).max(0.0);
+            let i = (g as usize).min(n - 1);
+            index[axis] = i;
+            frac[axis] = if i + 1 < n { g - i as Scalar } else { 0.0 };
+        }
+
+        let at = |x: usize, y: usize, z: usize| -> Scalar {
+            let x = (index[0] + x).min(self.resolution[0] - 1);
+            let y = (index[1] + y).min(self.resolution[1] - 1);
+            let z = (index[2] + z).min(self.resolution[2] - 1);
+            channel[(z * self.resolution[1] + y) * self.resolution[0] + x]
+        };
+
+        let lerp = |a: Scalar, b: Scalar, t: Scalar| a + (b - a) * t;
+        let (fx, fy, fz) = (frac[0], frac[1], frac[2]);
+
+        lerp(
+            lerp(
+                lerp(at(0, 0, 0), at(1, 0, 0), fx),
+                lerp(at(0, 1, 0), at(1, 1, 0), fx),
+                fy,
+            ),
+            lerp(
+                lerp(at(0, 0, 1), at(1, 0, 1), fx),
+                lerp(at(0, 1, 1), at(1, 1, 1), fx),
+                fy,
+            ),
+            fz,
+        )
+    }
+
+    /// Density of the grid at a point in world space
+    pub fn density(&self, p: &Point3) -> Scalar {
+        self.lookup(&self.density, p)
+    }
+
+    /// Temperature of the grid in kelvin at a point in world space
+    pub fn temperature(&self, p: &Point3) -> Scalar {
+        self.temperature
+            .as_ref()
+            .map_or(0.0, |channel| self.lookup(channel, p))
+    }
+
+    /// Emission of the grid at a point in world space
+    pub fn emission(&self, p: &Point3) -> Scalar {
+        self.emission
+            .as_ref()
+            .map_or(0.0, |channel| self.lookup(channel, p))
+    }
+
+    /// Determine the span of parameters along a ray lying within the bounds of the grid
+    fn intersect(&self, ray: &Ray, t_min: Scalar, t_max: Scalar) -> Option<(Scalar, Scalar)> {
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+
+        for axis in 0..3 {
+            let inv = 1.0 / ray.direction[axis];
+            let mut near = (self.min[axis] - ray.origin[axis]) * inv;
+            let mut far = (self.max[axis] - ray.origin[axis]) * inv;
+            if near > far {
+                std::mem::swap(&mut near, &mut far);
+            }
+
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+            if t0 >= t1 {
+                return None;
+            }
+        }
+
+        Some((t0, t1))
+    }
+}
+
+/// Heterogeneous medium whose density is given by a voxel grid, sampled with delta tracking and
+/// with transmittance estimated by ratio tracking
+#[derive(Debug, Clone)]
+pub struct GridMedium {
+    pub grid: Arc<VoxelGrid>,
+    pub density_scale: Scalar,
+    pub material: Arc<dyn Material>,
+}
+
+impl Shape for GridMedium {
+    /// Does an incoming ray intersect this shape
+    fn hit(&self, ray: &Ray, t_min: Scalar, t_max: Scalar) -> Option<HitResult> {
+        let (t0, t1) = self.grid.intersect(ray, t_min, t_max)?;
+        let majorant = self.grid.max_density * self.density_scale;
+        if majorant <= 0.0 {
+            return None;
+        }
+
+        // Take exponentially distributed steps against the majorant, accepting each tentative
+        // collision as real with probability proportional to the local density
+        let mut t = t0;
+        loop {
+            t -= (1.0 - random::<Scalar>()).ln() / majorant;
+            if t >= t1 {
+                return None;
+            }
+
+            let p = ray.at(t);
+            if random::<Scalar>() * majorant < self.grid.density(&p) * self.density_scale {
+                return Some(HitResult {
+                    t,
+                    p,
+                    normal: -ray.direction,
+                    uv: Point2::origin(),
+                    tangent: Vector3::zeros(),
+                    bitangent: Vector3::zeros(),
+                    material: self.material.clone(),
+                });
+            }
+        }
+    }
+
+    /// Fraction of light passing through this shape along a ray between t_min and t_max
+    fn transmittance(&self, ray: &Ray, t_min: Scalar, t_max: Scalar) -> Scalar {
+        let (t0, t1) = match self.grid.intersect(ray, t_min, t_max) {
+            Some(span) => span,
+            None => return 1.0,
+        };
+        let majorant = self.grid.max_density * self.density_scale;
+        if majorant <= 0.0 {
+            return 1.0;
+        }
+
+        // Ratio tracking: rather than terminating at the first real collision, attenuate by the
+        // probability of each tentative collision being a null collision
+        let mut transmittance = 1.0;
+        let mut t = t0;
+        loop {
+            t -= (1.0 - random::<Scalar>()).ln() / majorant;
+            if t >= t1 {
+                return transmittance;
+            }
+
+            let density = self.grid.density(&ray.at(t)) * self.density_scale;
+            transmittance *= 1.0 - density / majorant;
+        }
+    }
+}
+
+/// Material of a voxel grid medium, scattering light and emitting it according to its
+/// emission and temperature channels
+#[derive(Debug, Clone)]
+pub struct GridMaterial {
+    pub grid: Arc<VoxelGrid>,
+    pub albedo: Color,
+    pub phase: Arc<dyn PhaseFunction>,
+    pub emission: Color,
+    pub temperature_scale: Scalar,
+}
+
+impl Material for GridMaterial {
+    /// Calculate scattered ray generated by an incoming ray interacting with this surface
+    fn scatter(&self, ray: &Ray, hit: &HitResult) -> Option<ScatteredRay> {
+        Some(ScatteredRay {
+            ray: Ray::new(hit.p, self.phase.sample(&ray.direction)),
+            attenuation: self.albedo,
+            kind: ScatterKind::Diffuse,
+        })
+    }
+
+    /// Light emitted by this surface at the point of intersection
+    fn emitted(&self, hit: &HitResult) -> Color {
+        // Radiators grow brighter with the fourth power of temperature, here relative to 1000K
+        let temperature = self.grid.temperature(&hit.p);
+        let radiance = self.emission * self.grid.emission(&hit.p)
+            + blackbody(temperature) * self.temperature_scale * (temperature / 1000.0).powi(4);
+
+        // Only the absorbed fraction of each collision contributes emitted light
+        Color::new(
+            radiance.r * (1.0 - self.albedo.r),
+            radiance.g * (1.0 - self.albedo.g),
+            radiance.b * (1.0 - self.albedo.b),
+            1.0,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Write a grid file with the given header lines and values, returning its path
+    fn write_grid(name: &str, header: &str, values: &[Scalar]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("rtxon-{}-{}.vol", name, std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(header.as_bytes()).unwrap();
+        for value in values {
+            file.write_all(&value.to_le_bytes()).unwrap();
+        }
+        path
+    }
+
+    fn load(name: &str, header: &str, values: &[Scalar]) -> Result<VoxelGrid, Error> {
+        let path = write_grid(name, header, values);
+        let grid = VoxelGrid::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        grid
+    }
+
+    #[test]
+    fn loads_a_valid_grid() {
+        let grid = load(
+            "valid",
+            "RTXVOL\n2 1 1\ndensity emission\n",
+            &[0.5, 1.0, 2.0, 0.0],
+        )
+        .unwrap();
+        assert_eq!(grid.resolution, [2, 1, 1]);
+        assert_eq!(grid.density, vec![0.5, 2.0]);
+        assert_eq!(grid.emission, Some(vec![1.0, 0.0]));
+        assert!(grid.temperature.is_none());
+        assert_eq!(grid.max_density, 2.0);
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        assert!(load("magic", "RTXVOX\n1 1 1\ndensity\n", &[1.0]).is_err());
+        assert!(load("zero", "RTXVOL\n1 0 1\ndensity\n", &[]).is_err());
+        assert!(load("axes", "RTXVOL\n1 1\ndensity\n", &[1.0]).is_err());
+        assert!(load("first", "RTXVOL\n1 1 1\nemission density\n", &[1.0, 1.0]).is_err());
+        assert!(load("unknown", "RTXVOL\n1 1 1\ndensity color\n", &[1.0, 1.0]).is_err());
+        assert!(load("twice", "RTXVOL\n1 1 1\ndensity density\n", &[1.0, 1.0]).is_err());
+    }
+
+    #[test]
+    fn rejects_resolutions_the_file_cannot_hold() {
+        assert!(load("short", "RTXVOL\n2 2 2\ndensity\n", &[1.0; 7]).is_err());
+        let huge = format!("RTXVOL\n{} {} 2\ndensity\n", usize::MAX / 2, usize::MAX / 2);
+        assert!(load("huge", &huge, &[1.0]).is_err());
+    }
+
+    #[test]
+    fn rejects_negative_and_non_finite_values() {
+        let header = "RTXVOL\n1 1 1\ndensity temperature\n";
+        assert!(load("negative", header, &[-1.0, 1000.0]).is_err());
+        assert!(load("cold", header, &[1.0, -1000.0]).is_err());
+        assert!(load("nan", header, &[Scalar::NAN, 1000.0]).is_err());
+        assert!(load("infinite", header, &[1.0, Scalar::INFINITY]).is_err());
+    }
+
+    #[test]
+    fn rejects_empty_bounds() {
+        let mut grid = VoxelGrid::from_fn([1, 1, 1], |_| (1.0, 0.0, 0.0));
+        assert!(grid
+            .place(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 1.0))
+            .is_err());
+        assert!(grid
+            .place(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0))
+            .is_ok());
+    }
+}
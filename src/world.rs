@@ -0,0 +1,25 @@
+use std::sync::Arc;
+
+use crate::environment::Environment;
+use crate::media::Fog;
+use crate::shapes::{Scene, Shape};
+use crate::types::{Ray, Scalar};
+
+/// A scene along with the global effects applied to rays travelling through it
+#[derive(Clone)]
+pub struct World {
+    pub scene: Scene,
+    pub fog: Option<Fog>,
+    pub environment: Arc<dyn Environment>,
+}
+
+impl World {
+    /// Fraction of light passing unobstructed along a ray up to a distance of t_max
+    pub fn transmittance(&self, ray: &Ray, t_max: Scalar) -> Scalar {
+        let fog = self
+            .fog
+            .as_ref()
+            .map_or(1.0, |fog| fog.transmittance(ray, t_max));
+        fog * self.scene.transmittance(ray, 0.001, t_max)
+    }
+}